@@ -14,247 +14,4246 @@
 //!
 //! In this contract, there are two parties, a buyer, and a seller. The buyer wants
 //! to buy some goods from the seller, and use this smart contract instance as trusted entity for
-//! depositing funds. The contract is instantiated by the buyer.
+//! depositing funds. The contract is instantiated by the buyer, or on their behalf (e.g. by the
+//! `escrow_factory` contract).
 //!
-//! This contract can end up in two ways:
+//! The deal is settled in one of the following ways:
 //! * either delivery of the goods is confirmed by the buyer -> a deposit is transferred to the seller, or
-//! * delivery is cancelled by the seller -> a deposit is refunded to the buyer.
-//! In each way contract terminates itself and any remaining funds are transferred to contract
-//! constructor account (the buyer)
+//! * delivery is cancelled by the seller -> a deposit is refunded to the buyer, or
+//! * a dispute raised by either party is resolved by the arbiter -> a deposit is split between
+//! the buyer and the seller as the arbiter decides. This is possible only if an arbiter was
+//! chosen when the contract was instantiated, or
+//! * either party proposes a different split of the deposit (e.g. a lower price), which is
+//! accepted by the other party before the proposal expires. In particular, when goods arrive
+//! partially damaged, the buyer can confirm only a part of the deposit with `confirm_partial()`,
+//! which the seller either accepts - then the rest is refunded - or escalates to a dispute.
+//!
+//! The progress of the deal is tracked by its state:
+//! * `Created` - the deal awaits acceptance of the seller (see collateral below),
+//! * `Funded` - the deal is active, the deposit is held by the contract,
+//! * `Disputed` - a dispute awaits resolution of the arbiter,
+//! * `Released` - the deal is settled and the seller has been paid,
+//! * `Refunded` - the deal is settled and the whole deposit has been returned to the buyer.
+//! A settled deal remains on-chain, so that it can be audited, until the buyer closes it. Then the
+//! contract terminates itself and any remaining funds, including the storage deposit, are
+//! transferred to the buyer.
+//!
+//! For atomic swaps, the deposit can be locked with a hash of a secret known to the seller. The
+//! seller claims the deposit by revealing the secret, which is then published in an event, so that
+//! it can be used on the other chain. The delivery deadline serves as a timeout, after which
+//! the seller can no longer claim and the buyer can reclaim the deposit.
+//!
+//! Optionally, the deal may be bounded in time, with deadlines expressed as block numbers:
+//! * after the delivery deadline the buyer can reclaim the deposit on their own, and
+//! * after the confirmation deadline the seller can collect the deposit if the buyer stays silent.
+//!
+//! The deal can refer to an order (by a hash of its details) and the seller can attach a hash of
+//! the delivery proof (e.g. IPFS CID of a shipping receipt) with `mark_shipped()`, up to
+//! the delivery deadline. Once the goods are shipped, the buyer can no longer reclaim the deposit
+//! after the delivery deadline - provided the seller can get paid without the buyer, i.e. the deal
+//! has a confirmation deadline or an arbiter. Instead of a fixed confirmation deadline, the deal
+//! may set a confirmation window, which starts when the goods are shipped. A deposit locked with
+//! a hash cannot be marked shipped, as the seller gets it only by revealing the secret, and for
+//! the same reason a confirmation window cannot be combined with a hashlock.
+//!
+//! The deposit can also be split into milestones, paid out to the seller one by one as the buyer
+//! confirms them. The deal is settled once the last milestone is confirmed, or when the
+//! remainder of the deposit is settled in any of the ways described above.
+//!
+//! For retainers, the deposit can instead be paid out as a subscription over a number of periods
+//! of equal length (in blocks), starting once the deal is funded. The seller claims one period at a time
+//! with `claim_period()`, once that period has elapsed. The buyer can cancel the subscription
+//! with `cancel()` - then the seller is paid for the periods elapsed so far, and the remainder
+//! is refunded. The deal is then considered released, unless the seller has not been paid
+//! anything under the subscription or vesting.
+//!
+//! Alternatively, the deposit can vest linearly between a start and an end timestamp. The seller
+//! withdraws the vested part at any time with `withdraw_vested()`. The buyer can cancel vesting
+//! with `cancel()` as well - then the seller is paid what has vested so far, and the unvested
+//! remainder is refunded.
+//!
+//! The seller can be required to post a collateral. In such case the deal becomes active only
+//! once the seller accepts it with `accept()`, transferring the collateral along. Until then,
+//! the buyer can reclaim the deposit at any time. The collateral is returned to the seller when
+//! the deal is settled, unless the seller misses the delivery deadline or the arbiter awards
+//! the whole deposit to the buyer - then the collateral is forfeited to the buyer.
+//!
+//! The deal can also be an item-for-payment trade of a PSP34 token (NFT). Then the seller, having
+//! approved the contract to transfer the token, deposits it with `accept()`. When the deal is
+//! released, the token is transferred to the buyer along with the payment to the seller; when
+//! the deal is refunded, the token is returned to the seller. Both the token and the payment are
+//! transferred only once the deal is settled, and if either transfer fails, the call traps - so
+//! the whole settlement, including the other transfer, is reverted.
+//!
+//! Confirmation and refund can also be submitted by a relayer, who pays the transaction fees,
+//! with `confirm_with_signature()` and `refund_with_signature()`. These take an ECDSA signature
+//! of the buyer or the seller respectively over the Blake2x256 hash of the SCALE-encoded tuple
+//! `(contract address, action, nonce)`, where the nonce is returned by `get_nonce()`. The nonce
+//! is bumped by every signed action, so a signature cannot be replayed. The signer's account is
+//! derived from the public key as its Blake2x256 hash, as Substrate does for ECDSA accounts.
+//!
+//! For high-value deals, the buyer can appoint approvers (e.g. members of their finance team).
+//! Once a threshold of approvers has approved the release with `approve_release()`, the deposit
+//! is transferred to the seller, just as if the buyer confirmed delivery. Until then, confirmation
+//! of the buyers is recorded, but does not release the deposit, and the buyer can neither confirm
+//! a milestone or a part of the deposit, nor settle on a split paying the seller anything. An
+//! approver can revoke their approval with `revoke_approval()` until the threshold is reached.
+//!
+//! Several buyers can pool a purchase: accounts listed in the terms can add to the deposit with
+//! `contribute()`, becoming contributors. The deal is released once a threshold of contributors
+//! (one by default) confirms delivery, and every refund to the buyers is distributed among
+//! contributors pro rata to their contributions. Other actions of the buyers are taken by the buyer
+//! who instantiated the contract. Payees and the fee collector cannot contribute, so that they
+//! cannot confirm delivery to themselves.
+//!
+//! The order can be fulfilled by several payees, e.g. suppliers, each entitled to a share of every
+//! payout (in basis points). The first payee is the lead seller, who takes all the other actions
+//! of the seller, and receives the rounding remainder. Any payee can waive their share with
+//! `waive()` - then it is refunded to the buyers instead. Once all payees waive, the deal is refunded.
+//!
+//! If the deal is made on a marketplace, a platform fee (in basis points) can be deducted from
+//! every payout to the seller and transferred to the fee collector.
+//!
+//! The buyer can top up the deposit at any time, e.g. when shipping costs turn out higher. With
+//! milestones, top-ups are released together with the last one.
+//!
+//! In case a vulnerability is found, an optional guardian can freeze the deal with `pause()`. While
+//! paused, the deal cannot be confirmed nor refunded, no funds nor tokens leave the contract and
+//! it cannot be closed, but it can still be queried. The guardian resumes the deal with `unpause()`.
+//!
+//! Settlement follows the checks-effects-interactions pattern: the deal is moved to its final state
+//! and the deposit is zeroed before any funds or tokens leave the contract, and calls to other
+//! contracts explicitly deny reentrancy. A receiver calling back into the contract finds the deal
+//! already settled. Since an error returned by a message does not revert its changes, any transfer
+//! failing after the deal is settled traps instead, reverting the whole settlement.
+//!
+//! Every transition of the deal emits a dedicated event (`Funded`, `Disputed`, `Resolved`,
+//! `Confirmed`, `Refunded`, `Terminated`) with the escrow, the buyer and the seller as topics,
+//! so that the history of deals can be reconstructed from events alone. Every movement of funds
+//! additionally emits a `Transfer` event.
 //!
 //! Implementation note: due to how things are implemented in Substrate, a storage deposit for this
-//! contract is returned to message caller. This means that the deposit made by contract instantiation
-//! account, which is the buyer, can be acquired by the seller by calling `refund()`. This value
-//! can be non-negligible on some chains.
+//! contract is returned to the beneficiary of contract termination. Hence the buyer, who made this
+//! deposit at instantiation, is always the beneficiary - otherwise e.g. the seller could acquire it.
+//! This value can be non-negligible on some chains.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+
+#[ink::contract]
+pub mod escrow {
+    use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+    use ink_env::hash::{Blake2x256, Sha2x256};
+    use ink_env::CallFlags;
+    use ink_prelude::{string::String, vec::Vec};
+    use ink_primitives::KeyPtr;
+    use ink_storage::{
+        traits::{PackedLayout, SpreadAllocate, SpreadLayout},
+        Mapping,
+    };
+    use scale::{Decode, Encode};
+
+    /// Basis points making up the whole payout
+    const MAX_BASIS_POINTS: u16 = 10_000;
+
+    /// Maximum number of accounts allowed to contribute besides the buyer, so that refunding all
+    /// contributors at once always fits in a block
+    const MAX_CONTRIBUTORS: usize = 16;
+
+    /// Selector of `PSP34::transfer`
+    const PSP34_TRANSFER_SELECTOR: [u8; 4] = [0x31, 0x28, 0xd6, 0x1b];
+
+    #[ink(storage)]
+    #[derive(SpreadAllocate)]
+    pub struct Escrow {
+        /// Buyer's account
+        buyer: AccountId,
+
+        /// Sellers's account, the lead payee
+        seller: AccountId,
+
+        /// Accounts among which payouts to the seller are split
+        payees: Vec<Payee>,
+
+        /// Deposit value
+        deposit: Balance,
+
+        /// Arbiter's account, if the parties agreed on one
+        arbiter: Option<AccountId>,
+
+        /// Current state of the deal
+        state: State,
+
+        /// Block after which the buyer can reclaim the deposit
+        delivery_deadline: Option<BlockNumber>,
+
+        /// Block after which the seller can collect the deposit
+        confirmation_deadline: Option<BlockNumber>,
+
+        /// Stages in which the deposit is released to the seller
+        milestones: Vec<Milestone>,
+
+        /// Settlement proposed by one party, awaiting acceptance of the other
+        proposal: Option<Proposal>,
+
+        /// Hash of a secret which the seller reveals to claim the deposit
+        hashlock: Option<Hashlock>,
+
+        /// Platform fee deducted from payouts to the seller
+        fee: Option<Fee>,
+
+        /// Collateral required from the seller
+        collateral: Balance,
+
+        /// PSP34 token traded for the deposit
+        nft: Option<Nft>,
+
+        /// Number of signed actions executed so far, included in signed messages
+        nonce: u32,
+
+        /// Accounts allowed to contribute to the deposit, besides the buyer
+        allowed_contributors: Vec<AccountId>,
+
+        /// Accounts which contributed to the deposit, in order of their first contribution
+        contributors: Vec<AccountId>,
+
+        /// Total contribution of each contributor
+        contributions: Mapping<AccountId, Balance>,
+
+        /// Number of contributors whose confirmation releases the deposit
+        confirmation_threshold: u32,
+
+        /// Contributors who have confirmed delivery so far
+        confirmations: Vec<AccountId>,
+
+        /// Periods in which the deposit is released to the seller
+        subscription: Option<Subscription>,
+
+        /// Time span over which the deposit vests linearly to the seller
+        vesting: Option<Vesting>,
+
+        /// Hash of the order details
+        order_ref: Option<Hash>,
+
+        /// Number of blocks, after shipping, within which the buyer should confirm delivery
+        confirmation_window: Option<BlockNumber>,
+
+        /// Shipment of the goods, once the seller has shipped them
+        shipment: Option<Shipment>,
+
+        /// Accounts entitled to approve the release of the deposit
+        approvers: Vec<AccountId>,
+
+        /// Number of approvals releasing the deposit
+        approval_threshold: u32,
+
+        /// Approvers who have approved the release so far
+        approvals: Vec<AccountId>,
+
+        /// Account entitled to pause the contract in an emergency
+        guardian: Option<AccountId>,
+
+        /// Whether settlements and payouts are blocked
+        paused: bool,
+    }
+
+    /// Shipment of the goods
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Shipment {
+        /// Hash of the delivery proof, e.g. IPFS CID of a shipping receipt
+        pub proof: Hash,
+
+        /// Block at which the goods were marked as shipped
+        pub shipped_at: BlockNumber,
+    }
+
+    /// Action authorized off-chain by a signature of a party
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum SignedAction {
+        /// Buyer confirms delivery
+        Confirm,
+
+        /// Seller refunds a deposit
+        Refund,
+    }
+
+    /// Identifier of a PSP34 token within its collection
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum Id {
+        U8(u8),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        U128(u128),
+        Bytes(Vec<u8>),
+    }
+
+    /// PSP34 token traded for the deposit
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Nft {
+        /// PSP34 contract of the collection
+        pub collection: AccountId,
+
+        /// Token identifier within the collection
+        pub id: Id,
+    }
+
+    /// Errors returned by PSP34 token contracts
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum PSP34Error {
+        Custom(String),
+        SelfApprove,
+        NotApproved,
+        TokenExists,
+        TokenNotExists,
+        SafeTransferCheckFailed(String),
+    }
+
+    /// State of the deal
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum State {
+        /// Deal awaits acceptance of the seller
+        Created,
+
+        /// Deal is active, the deposit is held by the contract
+        Funded,
+
+        /// Dispute awaits resolution of the arbiter
+        Disputed,
+
+        /// Deal is settled, the seller has been paid
+        Released,
+
+        /// Deal is settled, the whole deposit has been returned to the buyer
+        Refunded,
+    }
+
+    impl SpreadAllocate for State {
+        fn allocate_spread(ptr: &mut KeyPtr) -> Self {
+            ptr.advance_by(<Self as SpreadLayout>::FOOTPRINT);
+            State::Created
+        }
+    }
+
+    /// Account entitled to a share of payouts to the seller
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Payee {
+        /// Payee's account
+        pub account: AccountId,
+
+        /// Share of every payout, in basis points
+        pub share_bps: u16,
+
+        /// Whether the payee has waived their share in favour of the buyers
+        pub waived: bool,
+    }
+
+    /// Periods in which the deposit is released to the seller
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Subscription {
+        /// Block at which the first period starts, i.e. at which the deal is funded
+        pub start: BlockNumber,
+
+        /// Length of a period, in blocks
+        pub period: BlockNumber,
+
+        /// Number of periods
+        pub count: u32,
+
+        /// Number of periods claimed by the seller so far
+        pub claimed: u32,
+    }
+
+    /// Time span over which the deposit vests linearly to the seller
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Vesting {
+        /// Timestamp at which vesting starts
+        pub start: Timestamp,
+
+        /// Timestamp at which the whole deposit is vested
+        pub end: Timestamp,
+
+        /// Part of the deposit withdrawn by the seller so far
+        pub released: Balance,
+    }
+
+    /// Platform fee deducted from payouts to the seller
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Fee {
+        /// Account collecting the fee
+        pub collector: AccountId,
+
+        /// Part of the payout taken as a fee, in basis points (hundredths of a percent)
+        pub basis_points: u16,
+    }
+
+    /// Hash of a secret, revealing which releases the deposit to the seller
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum Hashlock {
+        /// SHA2-256 hash of the secret
+        Sha2x256([u8; 32]),
+
+        /// BLAKE2b-256 hash of the secret
+        Blake2x256([u8; 32]),
+    }
+
+    /// Part of the deposit released to the seller once the buyer confirms a stage of the deal
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Milestone {
+        /// Value released to the seller
+        pub amount: Balance,
+
+        /// Hash of the milestone description kept off-chain
+        pub description_hash: Hash,
+
+        /// Whether the milestone has already been paid out
+        pub released: bool,
+    }
+
+    /// Split of the deposit proposed by one party to the other
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Proposal {
+        /// Party which made the proposal
+        pub proposer: AccountId,
+
+        /// Part of the deposit returned to the buyer
+        pub buyer_share: Balance,
+
+        /// Part of the deposit transferred to the seller
+        pub seller_share: Balance,
+
+        /// Last block in which the proposal can be accepted
+        pub expires_at: BlockNumber,
+    }
+
+    /// Deposit and collateral compared with the actual balance of the contract. If the balance
+    /// differs from their sum, funds were transferred to the contract directly, bypassing `new()`,
+    /// `top_up()`, `contribute()` and `accept()`
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct BalanceReport {
+        /// Deposit recorded by the contract
+        pub deposit: Balance,
+
+        /// Collateral posted by the seller and held by the contract
+        pub collateral: Balance,
+
+        /// Actual free balance of the contract
+        pub balance: Balance,
+    }
+
+    /// Optional terms of the deal, agreed by the buyer and the seller upfront
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Terms {
+        /// Buyer's account, if different from the contract instantiator
+        pub buyer: Option<AccountId>,
+
+        /// Account trusted by both parties to resolve disputes
+        pub arbiter: Option<AccountId>,
+
+        /// Block after which the buyer can reclaim the deposit if the deal is not settled
+        pub delivery_deadline: Option<BlockNumber>,
+
+        /// Block after which the seller can collect the deposit if the buyer has not confirmed.
+        /// Must be later than the delivery deadline, if both are set
+        pub confirmation_deadline: Option<BlockNumber>,
+
+        /// Amounts and description hashes of the milestones. If not empty, amounts must sum up
+        /// to the deposit
+        pub milestones: Vec<(Balance, Hash)>,
+
+        /// Hash of a secret which the seller reveals to claim the deposit. Requires the delivery
+        /// deadline, which serves as a timeout
+        pub hashlock: Option<Hashlock>,
+
+        /// Platform fee deducted from payouts to the seller
+        pub fee: Option<Fee>,
+
+        /// Collateral which the seller must post to accept the deal. Zero means no collateral
+        pub collateral: Balance,
+
+        /// PSP34 token which the seller must deposit to accept the deal
+        pub nft: Option<Nft>,
+
+        /// Accounts allowed to contribute to the deposit, besides the buyer. At most 16 accounts,
+        /// which cannot include payees nor the fee collector
+        pub contributors: Vec<AccountId>,
+
+        /// Number of contributors whose confirmation releases the deposit. Zero means one
+        pub confirmation_threshold: u32,
+
+        /// Length of a period (in blocks) and number of periods, if the deposit is to be paid
+        /// out as a subscription. Cannot be combined with milestones
+        pub subscription: Option<(BlockNumber, u32)>,
+
+        /// Start and end timestamps, if the deposit is to vest linearly between them. Cannot be
+        /// combined with milestones nor a subscription
+        pub vesting: Option<(Timestamp, Timestamp)>,
+
+        /// Hash of the order details
+        pub order_ref: Option<Hash>,
+
+        /// Number of blocks, after the goods are shipped, after which the seller can collect
+        /// the deposit if the buyer has not confirmed. Cannot be combined with the confirmation
+        /// deadline nor a hashlock
+        pub confirmation_window: Option<BlockNumber>,
+
+        /// Accounts entitled to approve the release of the deposit
+        pub approvers: Vec<AccountId>,
+
+        /// Number of approvals releasing the deposit. Must be between one and the number
+        /// of approvers, if there are any
+        pub approval_threshold: u32,
+
+        /// Account entitled to pause the contract in an emergency
+        pub guardian: Option<AccountId>,
+    }
+
+    /// Error scenarios in escrow contract
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Someone else than a contributor tries to confirm
+        ConfirmNotAsBuyer,
+
+        /// Contributor tries to confirm for the second time
+        AlreadyConfirmed,
+
+        /// Contribution with no value transferred
+        ZeroContribution,
+
+        /// Account not allowed by the terms tries to contribute
+        ContributeNotAllowed,
+
+        /// Someone else than a payee tries to waive a share
+        WaiveNotAsPayee,
+
+        /// Payee tries to waive their share for the second time
+        AlreadyWaived,
+
+        /// Someone else than seller tries to refund
+        RefundNotAsSeller,
+
+        /// requested transfer failed, this can be the case if the contract does not
+        /// have sufficient free funds or if the transfer would have brought the
+        /// contract's balance below minimum balance
+        TransferFailed,
+
+        /// Someone else than buyer or seller tries to raise a dispute
+        DisputeNotAsParty,
+
+        /// Dispute is raised, but no arbiter was chosen for this contract
+        NoArbiter,
+
+        /// Dispute has already been raised
+        AlreadyDisputed,
+
+        /// Someone else than arbiter tries to resolve a dispute
+        ResolveNotAsArbiter,
+
+        /// Arbiter tries to resolve a dispute which has not been raised
+        NotDisputed,
+
+        /// Proposed shares do not sum up to the deposit
+        InvalidShares,
+
+        /// Someone else than buyer tries to reclaim a deposit
+        ReclaimNotAsBuyer,
+
+        /// Someone else than seller tries to collect a deposit
+        CollectNotAsSeller,
+
+        /// Buyer tries to reclaim a deposit before the delivery deadline has passed
+        DeliveryDeadlineNotPassed,
+
+        /// Seller tries to collect a deposit before the confirmation deadline has passed
+        ConfirmationDeadlineNotPassed,
+
+        /// Requested action is not allowed while a dispute awaits resolution
+        Disputed,
+
+        /// There is no milestone with a given index
+        MilestoneNotFound,
+
+        /// Milestone with a given index has already been paid out
+        MilestoneAlreadyReleased,
+
+        /// Someone else than buyer or seller tries to propose a settlement
+        ProposeNotAsParty,
+
+        /// Proposal would expire before it can be accepted
+        InvalidExpiry,
+
+        /// Someone else than the counterparty of the proposer tries to accept a settlement
+        AcceptNotAsCounterparty,
+
+        /// There is no settlement proposal to accept
+        NoProposal,
+
+        /// Settlement proposal has expired
+        ProposalExpired,
+
+        /// Arithmetic operation on balances overflowed
+        Overflow,
+
+        /// Someone else than seller tries to accept the deal
+        AcceptNotAsSeller,
+
+        /// Seller tries to accept the deal for the second time
+        AlreadyAccepted,
+
+        /// Transferred value is different than the collateral required
+        InvalidCollateral,
+
+        /// Requested action requires the seller to accept the deal first
+        NotAccepted,
+
+        /// Token contract rejects the transfer of the PSP34 token
+        NftTransferRejected(PSP34Error),
+
+        /// Call to the PSP34 token contract failed, e.g. the contract does not exist or it trapped
+        NftCallFailed,
+
+        /// Deal has already been settled
+        AlreadySettled,
+
+        /// Someone else than buyer tries to close the contract
+        CloseNotAsBuyer,
+
+        /// Buyer tries to close the contract before the deal is settled
+        NotSettled,
+
+        /// Someone else than buyer tries to top up a deposit
+        TopUpNotAsBuyer,
+
+        /// Someone else than seller tries to claim a deposit
+        ClaimNotAsSeller,
+
+        /// Seller tries to claim a deposit, but it is not locked with a hash
+        NoHashlock,
+
+        /// Revealed secret does not match the hashlock
+        InvalidSecret,
+
+        /// Seller tries to claim a deposit after the timeout has passed
+        HashlockExpired,
+
+        /// Signature is malformed or was not made by the required party
+        InvalidSignature,
+
+        /// Someone else than seller tries to claim a period
+        ClaimPeriodNotAsSeller,
+
+        /// Someone else than buyer tries to cancel a subscription
+        CancelNotAsBuyer,
+
+        /// Requested action requires the deposit to be paid out as a subscription or to vest
+        NoSchedule,
+
+        /// Seller tries to claim a period which has not elapsed yet
+        PeriodNotElapsed,
+
+        /// Someone else than seller tries to withdraw the vested deposit
+        WithdrawNotAsSeller,
+
+        /// Seller tries to withdraw, but nothing has vested since the last withdrawal
+        NothingVested,
+
+        /// Someone else than seller tries to mark the goods as shipped
+        ShipNotAsSeller,
+
+        /// Seller tries to mark the goods as shipped for the second time, or buyer tries to
+        /// reclaim a deposit after the goods have been shipped
+        AlreadyShipped,
+
+        /// Seller tries to mark the goods as shipped after the delivery deadline has passed
+        DeliveryDeadlinePassed,
+
+        /// Seller tries to mark the goods as shipped, but the deposit is locked with a hash
+        Hashlocked,
+
+        /// Someone else than an approver tries to approve or revoke approval of the release
+        ApproveNotAsApprover,
+
+        /// Approver tries to approve the release for the second time
+        AlreadyApproved,
+
+        /// Approver tries to revoke an approval which they have not given
+        NotApproved,
+
+        /// Buyer tries to pay the seller before the threshold of approvals is reached
+        ApprovalsPending,
+
+        /// Someone else than guardian tries to pause or unpause the contract
+        PauseNotAsGuardian,
+
+        /// Requested action is blocked, because the guardian has paused the contract
+        Paused,
+
+        /// Guardian tries to unpause the contract which is not paused
+        NotPaused,
+    }
+
+    /// An event emitted when token transfer occurs
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        to: AccountId,
+        value: Balance,
+    }
+
+    /// An event emitted when someone contributes to the deposit
+    #[ink(event)]
+    pub struct Contributed {
+        #[ink(topic)]
+        contributor: AccountId,
+        value: Balance,
+    }
+
+    /// An event emitted when a contributor confirms delivery
+    #[ink(event)]
+    pub struct ConfirmationAdded {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    /// An event emitted when the deal becomes active, i.e. the deposit is held by the contract
+    #[ink(event)]
+    pub struct Funded {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+        deposit: Balance,
+        order_ref: Option<Hash>,
+    }
+
+    /// An event emitted when the seller marks the goods as shipped
+    #[ink(event)]
+    pub struct Shipped {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+        proof: Hash,
+        confirmation_deadline: Option<BlockNumber>,
+    }
+
+    /// An event emitted when the deal is settled by releasing the deposit to the seller
+    #[ink(event)]
+    pub struct Confirmed {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+    }
+
+    /// An event emitted when the deal is settled by refunding the deposit to the buyer
+    #[ink(event)]
+    pub struct Refunded {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+    }
+
+    /// An event emitted when the buyer closes a settled deal and the contract terminates
+    #[ink(event)]
+    pub struct Terminated {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+    }
+
+    /// An event emitted when the seller accepts the deal, posting the collateral
+    #[ink(event)]
+    pub struct Accepted {
+        #[ink(topic)]
+        seller: AccountId,
+        collateral: Balance,
+    }
+
+    /// An event emitted when the guardian pauses the contract
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        guardian: AccountId,
+    }
+
+    /// An event emitted when the guardian unpauses the contract
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        guardian: AccountId,
+    }
+
+    /// An event emitted when an approver approves the release of the deposit
+    #[ink(event)]
+    pub struct ReleaseApproved {
+        #[ink(topic)]
+        approver: AccountId,
+        approvals: u32,
+    }
+
+    /// An event emitted when an approver revokes their approval
+    #[ink(event)]
+    pub struct ApprovalRevoked {
+        #[ink(topic)]
+        approver: AccountId,
+        approvals: u32,
+    }
+
+    /// An event emitted when a payee waives their share
+    #[ink(event)]
+    pub struct Waived {
+        #[ink(topic)]
+        payee: AccountId,
+    }
+
+    /// An event emitted when a platform fee is paid to the fee collector
+    #[ink(event)]
+    pub struct FeePaid {
+        #[ink(topic)]
+        collector: AccountId,
+        value: Balance,
+    }
+
+    /// An event emitted when the buyer confirms a milestone
+    #[ink(event)]
+    pub struct MilestoneConfirmed {
+        #[ink(topic)]
+        index: u32,
+        value: Balance,
+    }
+
+    /// An event emitted when either party raises a dispute
+    #[ink(event)]
+    pub struct Disputed {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+        by: AccountId,
+    }
+
+    /// An event emitted when the arbiter resolves a dispute
+    #[ink(event)]
+    pub struct Resolved {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+        buyer_share: Balance,
+        seller_share: Balance,
+    }
+
+    /// An event emitted when the seller reveals the secret of the hashlock
+    #[ink(event)]
+    pub struct SecretRevealed {
+        secret: Vec<u8>,
+    }
+
+    /// An event emitted when either party proposes a settlement
+    #[ink(event)]
+    pub struct SettlementProposed {
+        #[ink(topic)]
+        proposer: AccountId,
+        buyer_share: Balance,
+        seller_share: Balance,
+        expires_at: BlockNumber,
+    }
+
+    /// An event emitted when a settlement proposal is accepted
+    #[ink(event)]
+    pub struct SettlementAccepted {
+        #[ink(topic)]
+        proposer: AccountId,
+        buyer_share: Balance,
+        seller_share: Balance,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl Escrow {
+        /// Instantiates new escrow contract with buyer as contract author. Payees are given with
+        /// their shares in basis points, which must be positive and sum up to the whole payout
+        #[ink(constructor, payable)]
+        pub fn new(payees: Vec<(AccountId, u16)>, terms: Terms) -> Self {
+            assert!(!payees.is_empty(), "At least one payee is required");
+            assert!(
+                payees.iter().all(|(_, share_bps)| *share_bps > 0),
+                "Payee shares must be positive"
+            );
+            assert!(
+                payees
+                    .iter()
+                    .enumerate()
+                    .all(|(i, (account, _))| payees[..i].iter().all(|(other, _)| other != account)),
+                "Payees must be unique"
+            );
+            assert_eq!(
+                payees
+                    .iter()
+                    .map(|(_, share_bps)| *share_bps as u32)
+                    .sum::<u32>(),
+                MAX_BASIS_POINTS as u32,
+                "Payee shares must sum up to the whole payout"
+            );
+            if let (Some(delivery), Some(confirmation)) =
+                (terms.delivery_deadline, terms.confirmation_deadline)
+            {
+                assert!(
+                    delivery < confirmation,
+                    "Delivery deadline must precede confirmation deadline"
+                );
+            }
+            assert!(
+                terms.confirmation_window.is_none() || terms.confirmation_deadline.is_none(),
+                "Confirmation window cannot be combined with confirmation deadline"
+            );
+            assert!(
+                terms.confirmation_window.is_none() || terms.hashlock.is_none(),
+                "Confirmation window cannot be combined with hashlock"
+            );
+            assert!(
+                terms.approval_threshold as usize <= terms.approvers.len()
+                    && (terms.approvers.is_empty() || terms.approval_threshold > 0),
+                "Approval threshold must be between one and the number of approvers"
+            );
+            assert!(
+                terms.hashlock.is_none() || terms.delivery_deadline.is_some(),
+                "Hashlock requires delivery deadline"
+            );
+            if let Some(fee) = terms.fee {
+                assert!(
+                    fee.basis_points <= MAX_BASIS_POINTS,
+                    "Fee cannot exceed the payout"
+                );
+            }
+            assert!(
+                terms.contributors.len() <= MAX_CONTRIBUTORS,
+                "Too many contributors"
+            );
+            // the buyer may contribute in addition to the listed accounts
+            assert!(
+                terms.confirmation_threshold as usize <= terms.contributors.len() + 1,
+                "Confirmation threshold cannot exceed the number of contributors"
+            );
+            assert!(
+                !terms.contributors.iter().any(|contributor| {
+                    payees.iter().any(|(payee, _)| payee == contributor)
+                        || terms.fee.map(|fee| fee.collector) == Some(*contributor)
+                }),
+                "Payees and the fee collector cannot contribute"
+            );
+            let deposit = Self::env().transferred_value();
+            if !terms.milestones.is_empty() {
+                let total = terms
+                    .milestones
+                    .iter()
+                    .try_fold(0 as Balance, |total, (amount, _)| {
+                        total.checked_add(*amount)
+                    });
+                assert_eq!(
+                    total,
+                    Some(deposit),
+                    "Milestones must sum up to the deposit"
+                );
+            }
+            if let Some((period, count)) = terms.subscription {
+                assert!(period > 0 && count > 0, "Subscription must have periods");
+                assert!(
+                    terms.milestones.is_empty(),
+                    "Subscription cannot be combined with milestones"
+                );
+            }
+            if let Some((start, end)) = terms.vesting {
+                assert!(start < end, "Vesting must start before it ends");
+                assert!(
+                    terms.milestones.is_empty() && terms.subscription.is_none(),
+                    "Vesting cannot be combined with milestones nor a subscription"
+                );
+            }
+            let buyer = terms.buyer.unwrap_or_else(|| Self::env().caller());
+            let seller = payees[0].0;
+            // otherwise the deal becomes active once the seller accepts it
+            let funded = terms.collateral == 0 && terms.nft.is_none();
+            Self::env().emit_event(Transfer {
+                to: Self::env().account_id(),
+                value: deposit,
+            });
+            if funded {
+                Self::env().emit_event(Funded {
+                    escrow: Self::env().account_id(),
+                    buyer,
+                    seller,
+                    deposit,
+                    order_ref: terms.order_ref,
+                });
+            }
+
+            ink_lang::utils::initialize_contract(|escrow: &mut Self| {
+                escrow.buyer = buyer;
+                escrow.seller = seller;
+                escrow.payees = payees
+                    .into_iter()
+                    .map(|(account, share_bps)| Payee {
+                        account,
+                        share_bps,
+                        waived: false,
+                    })
+                    .collect();
+                escrow.deposit = deposit;
+                escrow.arbiter = terms.arbiter;
+                escrow.state = if funded {
+                    State::Funded
+                } else {
+                    State::Created
+                };
+                escrow.delivery_deadline = terms.delivery_deadline;
+                escrow.confirmation_deadline = terms.confirmation_deadline;
+                escrow.milestones = terms
+                    .milestones
+                    .into_iter()
+                    .map(|(amount, description_hash)| Milestone {
+                        amount,
+                        description_hash,
+                        released: false,
+                    })
+                    .collect();
+                escrow.hashlock = terms.hashlock;
+                escrow.fee = terms.fee;
+                escrow.collateral = terms.collateral;
+                escrow.nft = terms.nft;
+                escrow.allowed_contributors = terms.contributors;
+                escrow.confirmation_threshold = terms.confirmation_threshold.max(1);
+                escrow.subscription = terms.subscription.map(|(period, count)| Subscription {
+                    start: Self::env().block_number(),
+                    period,
+                    count,
+                    claimed: 0,
+                });
+                escrow.vesting = terms.vesting.map(|(start, end)| Vesting {
+                    start,
+                    end,
+                    released: 0,
+                });
+                escrow.order_ref = terms.order_ref;
+                escrow.confirmation_window = terms.confirmation_window;
+                escrow.approvers = terms.approvers;
+                escrow.approval_threshold = terms.approval_threshold;
+                escrow.guardian = terms.guardian;
+                escrow.contributors.push(buyer);
+                escrow.contributions.insert(buyer, &deposit);
+            })
+        }
+
+        /// Returns currently stored deposit
+        #[ink(message)]
+        pub fn get_deposit(&self) -> Balance {
+            self.deposit
+        }
+
+        /// Returns currently stored deposit and collateral along with the actual balance
+        /// of the contract
+        #[ink(message)]
+        pub fn get_balance_report(&self) -> BalanceReport {
+            // collateral is posted on acceptance and paid out on settlement
+            let collateral = match self.state {
+                State::Funded | State::Disputed => self.collateral,
+                State::Created | State::Released | State::Refunded => 0,
+            };
+            BalanceReport {
+                deposit: self.deposit,
+                collateral,
+                balance: self.env().balance(),
+            }
+        }
+
+        /// Returns buyers account
+        #[ink(message)]
+        pub fn get_buyer(&self) -> AccountId {
+            self.buyer
+        }
+
+        /// Returns sellers account, the lead payee
+        #[ink(message)]
+        pub fn get_seller(&self) -> AccountId {
+            self.seller
+        }
+
+        /// Returns payees of the deal
+        #[ink(message)]
+        pub fn get_payees(&self) -> Vec<Payee> {
+            self.payees.clone()
+        }
+
+        /// Returns arbiter's account, if any
+        #[ink(message)]
+        pub fn get_arbiter(&self) -> Option<AccountId> {
+            self.arbiter
+        }
+
+        /// Returns current state of the deal
+        #[ink(message)]
+        pub fn get_state(&self) -> State {
+            self.state
+        }
+
+        /// Returns block after which the buyer can reclaim the deposit, if any
+        #[ink(message)]
+        pub fn get_delivery_deadline(&self) -> Option<BlockNumber> {
+            self.delivery_deadline
+        }
+
+        /// Returns block after which the seller can collect the deposit, if any
+        #[ink(message)]
+        pub fn get_confirmation_deadline(&self) -> Option<BlockNumber> {
+            self.confirmation_deadline
+        }
+
+        /// Returns pending settlement proposal, if any
+        #[ink(message)]
+        pub fn get_proposal(&self) -> Option<Proposal> {
+            self.proposal.clone()
+        }
+
+        /// Returns hashlock of the deposit, if any
+        #[ink(message)]
+        pub fn get_hashlock(&self) -> Option<Hashlock> {
+            self.hashlock
+        }
+
+        /// Returns platform fee, if any
+        #[ink(message)]
+        pub fn get_fee(&self) -> Option<Fee> {
+            self.fee
+        }
+
+        /// Returns collateral required from the seller
+        #[ink(message)]
+        pub fn get_collateral(&self) -> Balance {
+            self.collateral
+        }
+
+        /// Returns PSP34 token traded for the deposit, if any
+        #[ink(message)]
+        pub fn get_nft(&self) -> Option<Nft> {
+            self.nft.clone()
+        }
+
+        /// Returns nonce to be included in the next signed action
+        #[ink(message)]
+        pub fn get_nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        /// Returns accounts allowed to contribute to the deposit, besides the buyer
+        #[ink(message)]
+        pub fn get_allowed_contributors(&self) -> Vec<AccountId> {
+            self.allowed_contributors.clone()
+        }
+
+        /// Returns accounts which contributed to the deposit
+        #[ink(message)]
+        pub fn get_contributors(&self) -> Vec<AccountId> {
+            self.contributors.clone()
+        }
+
+        /// Returns total contribution of an account
+        #[ink(message)]
+        pub fn get_contribution(&self, contributor: AccountId) -> Balance {
+            self.contributions.get(contributor).unwrap_or_default()
+        }
+
+        /// Returns number of contributors whose confirmation releases the deposit
+        #[ink(message)]
+        pub fn get_confirmation_threshold(&self) -> u32 {
+            self.confirmation_threshold
+        }
+
+        /// Returns contributors who have confirmed delivery so far
+        #[ink(message)]
+        pub fn get_confirmations(&self) -> Vec<AccountId> {
+            self.confirmations.clone()
+        }
+
+        /// Returns milestones of the deal
+        #[ink(message)]
+        pub fn get_milestones(&self) -> Vec<Milestone> {
+            self.milestones.clone()
+        }
+
+        /// Returns hash of the order details, if any
+        #[ink(message)]
+        pub fn get_order_ref(&self) -> Option<Hash> {
+            self.order_ref
+        }
+
+        /// Returns number of blocks after shipping within which the buyer should confirm, if any
+        #[ink(message)]
+        pub fn get_confirmation_window(&self) -> Option<BlockNumber> {
+            self.confirmation_window
+        }
+
+        /// Returns shipment of the goods, if they have been shipped
+        #[ink(message)]
+        pub fn get_shipment(&self) -> Option<Shipment> {
+            self.shipment
+        }
+
+        /// Returns accounts entitled to approve the release of the deposit
+        #[ink(message)]
+        pub fn get_approvers(&self) -> Vec<AccountId> {
+            self.approvers.clone()
+        }
+
+        /// Returns number of approvals releasing the deposit
+        #[ink(message)]
+        pub fn get_approval_threshold(&self) -> u32 {
+            self.approval_threshold
+        }
+
+        /// Returns approvers who have approved the release so far
+        #[ink(message)]
+        pub fn get_approvals(&self) -> Vec<AccountId> {
+            self.approvals.clone()
+        }
+
+        /// Returns guardian's account, if any
+        #[ink(message)]
+        pub fn get_guardian(&self) -> Option<AccountId> {
+            self.guardian
+        }
+
+        /// Returns whether the guardian has paused the contract
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// The guardian pauses the contract, blocking settlements and payouts. Pausing
+        /// a paused contract has no effect
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if Some(caller) != self.guardian {
+                return Err(Error::PauseNotAsGuardian);
+            }
+            if !self.paused {
+                self.paused = true;
+                self.env().emit_event(Paused { guardian: caller });
+            }
+            Ok(())
+        }
+
+        /// The guardian unpauses the contract
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if Some(caller) != self.guardian {
+                return Err(Error::PauseNotAsGuardian);
+            }
+            if !self.paused {
+                return Err(Error::NotPaused);
+            }
+            self.paused = false;
+            self.env().emit_event(Unpaused { guardian: caller });
+            Ok(())
+        }
+
+        /// Returns subscription of the deal, if any
+        #[ink(message)]
+        pub fn get_subscription(&self) -> Option<Subscription> {
+            self.subscription
+        }
+
+        /// Returns vesting of the deal, if any
+        #[ink(message)]
+        pub fn get_vesting(&self) -> Option<Vesting> {
+            self.vesting
+        }
+
+        /// Returns part of the deposit vested so far and not withdrawn yet. Nothing is releasable
+        /// once the deal is settled
+        #[ink(message)]
+        pub fn releasable(&self) -> Result<Balance> {
+            let vesting = self.vesting.ok_or(Error::NoSchedule)?;
+            if self.is_settled() {
+                return Ok(0);
+            }
+            let now = self
+                .env()
+                .block_timestamp()
+                .clamp(vesting.start, vesting.end);
+            let total = self
+                .deposit
+                .checked_add(vesting.released)
+                .ok_or(Error::Overflow)?;
+            let vested = total
+                .checked_mul((now - vesting.start) as Balance)
+                .ok_or(Error::Overflow)?
+                / (vesting.end - vesting.start) as Balance;
+            Ok(vested.saturating_sub(vesting.released))
+        }
+
+        /// The seller accepts the deal, transferring the collateral along. If the deal is a trade
+        /// of a PSP34 token, contract pulls the token from the seller, who must have approved
+        /// the contract to transfer it beforehand
+        #[ink(message, payable)]
+        pub fn accept(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::AcceptNotAsSeller);
+            }
+            if self.state != State::Created {
+                return Err(Error::AlreadyAccepted);
+            }
+            self.ensure_not_paused()?;
+            if self.env().transferred_value() != self.collateral {
+                return Err(Error::InvalidCollateral);
+            }
+            if let Some(nft) = self.nft.clone() {
+                self.nft_transfer(&nft, self.env().account_id())?;
+            }
+            self.state = State::Funded;
+            if let Some(subscription) = self.subscription.as_mut() {
+                subscription.start = self.env().block_number();
+            }
+            self.env().emit_event(Accepted {
+                seller: caller,
+                collateral: self.collateral,
+            });
+            self.env().emit_event(Funded {
+                escrow: self.env().account_id(),
+                buyer: self.buyer,
+                seller: self.seller,
+                deposit: self.deposit,
+                order_ref: self.order_ref,
+            });
+            Ok(())
+        }
+
+        /// A contributor confirms delivery. Once the threshold of confirmations is reached,
+        /// and the threshold of approvals if there are approvers, contract transfers a deposit
+        /// to the seller. Payees and the fee collector never count
+        /// as contributors.
+        /// If the deal has milestones, all of those not yet released are paid out at once
+        #[ink(message)]
+        pub fn confirm(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if self.contributions.get(caller).is_none() || self.is_beneficiary(caller) {
+                return Err(Error::ConfirmNotAsBuyer);
+            }
+            self.confirm_as(caller)
+        }
+
+        /// Same as `confirm()` by the buyer, but can be called by anyone holding their signature
+        #[ink(message)]
+        pub fn confirm_with_signature(&mut self, signature: [u8; 65]) -> Result<()> {
+            self.ensure_not_paused()?;
+            self.verify_signature(self.buyer, SignedAction::Confirm, &signature)?;
+            self.confirm_as(self.buyer)
+        }
+
+        /// The buyer confirms a single milestone. Contract transfers its amount to the seller.
+        /// The deal is settled once all milestones are released
+        #[ink(message)]
+        pub fn confirm_milestone(&mut self, index: u32) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer {
+                return Err(Error::ConfirmNotAsBuyer);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            let milestone = self
+                .milestones
+                .get(index as usize)
+                .ok_or(Error::MilestoneNotFound)?;
+            if milestone.released {
+                return Err(Error::MilestoneAlreadyReleased);
+            }
+            // the last milestone takes along any top-ups
+            let is_last = self
+                .milestones
+                .iter()
+                .filter(|milestone| !milestone.released)
+                .count()
+                == 1;
+            let value = if is_last {
+                self.deposit
+            } else {
+                milestone.amount
+            };
+
+            self.ensure_accepted()?;
+            if !self.is_approved() {
+                return Err(Error::ApprovalsPending);
+            }
+            self.deposit -= value;
+            self.milestones[index as usize].released = true;
+            self.env().emit_event(MilestoneConfirmed { index, value });
+
+            if self.milestones.iter().all(|milestone| milestone.released) {
+                self.settle(State::Released, self.seller);
+            }
+            self.pay_seller(value);
+            Ok(())
+        }
+
+        /// The buyer increases a deposit by transferred value
+        #[ink(message, payable)]
+        pub fn top_up(&mut self) -> Result<()> {
+            if Self::env().caller() != self.buyer {
+                return Err(Error::TopUpNotAsBuyer);
+            }
+            self.ensure_not_settled()?;
+            self.add_contribution(self.buyer, self.env().transferred_value());
+            Ok(())
+        }
+
+        /// The buyer, or an account allowed by the terms, increases a deposit by transferred value,
+        /// becoming a contributor
+        #[ink(message, payable)]
+        pub fn contribute(&mut self) -> Result<()> {
+            let contributor = self.env().caller();
+            if contributor != self.buyer && !self.allowed_contributors.contains(&contributor) {
+                return Err(Error::ContributeNotAllowed);
+            }
+            self.ensure_not_settled()?;
+            let value = self.env().transferred_value();
+            if value == 0 {
+                return Err(Error::ZeroContribution);
+            }
+            self.add_contribution(contributor, value);
+            self.env().emit_event(Contributed { contributor, value });
+            Ok(())
+        }
+
+        /// An approver approves the release of the deposit. Once the threshold of approvals is
+        /// reached, contract transfers a deposit to the seller
+        #[ink(message)]
+        pub fn approve_release(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if !self.approvers.contains(&caller) {
+                return Err(Error::ApproveNotAsApprover);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            if self.approvals.contains(&caller) {
+                return Err(Error::AlreadyApproved);
+            }
+            self.approvals.push(caller);
+            let approvals = self.approvals.len() as u32;
+            self.env().emit_event(ReleaseApproved {
+                approver: caller,
+                approvals,
+            });
+            if !self.is_approved() {
+                return Ok(());
+            }
+            self.release_deposit()
+        }
+
+        /// An approver revokes their approval of the release
+        #[ink(message)]
+        pub fn revoke_approval(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if !self.approvers.contains(&caller) {
+                return Err(Error::ApproveNotAsApprover);
+            }
+            self.ensure_not_settled()?;
+            let index = self
+                .approvals
+                .iter()
+                .position(|approver| *approver == caller)
+                .ok_or(Error::NotApproved)?;
+            self.approvals.remove(index);
+            self.env().emit_event(ApprovalRevoked {
+                approver: caller,
+                approvals: self.approvals.len() as u32,
+            });
+            Ok(())
+        }
+
+        /// The seller aborts transaction. Contract refunds a deposit to the contributors.
+        /// If the deal has milestones, only the part not yet released is refunded
+        #[ink(message)]
+        pub fn refund(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::RefundNotAsSeller);
+            }
+            self.do_refund()
+        }
+
+        /// Same as `refund()`, but can be called by anyone holding the seller's signature
+        #[ink(message)]
+        pub fn refund_with_signature(&mut self, signature: [u8; 65]) -> Result<()> {
+            self.ensure_not_paused()?;
+            self.verify_signature(self.seller, SignedAction::Refund, &signature)?;
+            self.do_refund()
+        }
+
+        /// A payee waives their share, which is refunded to the buyers instead. If all payees have
+        /// waived their shares, the deal is refunded
+        #[ink(message)]
+        pub fn waive(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            let index = self
+                .payees
+                .iter()
+                .position(|payee| payee.account == caller)
+                .ok_or(Error::WaiveNotAsPayee)?;
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.payees[index].waived {
+                return Err(Error::AlreadyWaived);
+            }
+            self.payees[index].waived = true;
+            self.env().emit_event(Waived { payee: caller });
+
+            if self.payees.iter().all(|payee| payee.waived) {
+                return self.do_refund();
+            }
+            Ok(())
+        }
+
+        /// The buyer reclaims a deposit once the delivery deadline has passed, unless the goods have
+        /// been shipped, or at any time before the seller accepts the deal. Contract refunds
+        /// a deposit to the buyer
+        #[ink(message)]
+        pub fn reclaim(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer {
+                return Err(Error::ReclaimNotAsBuyer);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.state != State::Created && !self.has_passed(self.delivery_deadline) {
+                return Err(Error::DeliveryDeadlineNotPassed);
+            }
+            // shipping blocks the refund only if the seller can be paid without the buyer
+            if self.shipment.is_some()
+                && (self.confirmation_deadline.is_some() || self.arbiter.is_some())
+            {
+                return Err(Error::AlreadyShipped);
+            }
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            self.refund_deposit(self.buyer)
+        }
+
+        /// The seller marks the goods as shipped before the delivery deadline, attaching a hash of
+        /// the delivery proof. Starts the confirmation window, if there is one
+        #[ink(message)]
+        pub fn mark_shipped(&mut self, proof: Hash) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::ShipNotAsSeller);
+            }
+            match self.state {
+                State::Created => return Err(Error::NotAccepted),
+                State::Funded => {}
+                State::Disputed => return Err(Error::Disputed),
+                State::Released | State::Refunded => return Err(Error::AlreadySettled),
+            }
+            if self.hashlock.is_some() {
+                return Err(Error::Hashlocked);
+            }
+            if self.shipment.is_some() {
+                return Err(Error::AlreadyShipped);
+            }
+            if self.has_passed(self.delivery_deadline) {
+                return Err(Error::DeliveryDeadlinePassed);
+            }
+            let shipped_at = self.env().block_number();
+            self.shipment = Some(Shipment { proof, shipped_at });
+            if let Some(window) = self.confirmation_window {
+                self.confirmation_deadline = Some(shipped_at + window);
+            }
+            self.env().emit_event(Shipped {
+                escrow: self.env().account_id(),
+                buyer: self.buyer,
+                seller: self.seller,
+                proof,
+                confirmation_deadline: self.confirmation_deadline,
+            });
+            Ok(())
+        }
+
+        /// The seller collects a deposit once the confirmation deadline has passed.
+        /// Contract transfers a deposit to the seller
+        #[ink(message)]
+        pub fn collect(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::CollectNotAsSeller);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if !self.has_passed(self.confirmation_deadline) {
+                return Err(Error::ConfirmationDeadlineNotPassed);
+            }
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            self.release_deposit()
+        }
+
+        /// The seller claims the next period of a subscription, once it has elapsed. Contract
+        /// transfers its part of the deposit to the seller. The deal is settled once all periods
+        /// are claimed
+        #[ink(message)]
+        pub fn claim_period(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::ClaimPeriodNotAsSeller);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            let mut subscription = self.subscription.ok_or(Error::NoSchedule)?;
+            if self.elapsed_periods(&subscription) <= subscription.claimed {
+                return Err(Error::PeriodNotElapsed);
+            }
+            // remaining periods share the deposit equally, the last one takes any remainder
+            let value = self.deposit / (subscription.count - subscription.claimed) as Balance;
+            self.ensure_accepted()?;
+            self.deposit -= value;
+            subscription.claimed += 1;
+            self.subscription = Some(subscription);
+
+            if subscription.claimed == subscription.count {
+                self.settle(State::Released, self.seller);
+            }
+            self.pay_seller(value);
+            Ok(())
+        }
+
+        /// The seller withdraws the part of the deposit vested so far. The deal is settled once
+        /// the whole deposit is withdrawn
+        #[ink(message)]
+        pub fn withdraw_vested(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::WithdrawNotAsSeller);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            let value = self.releasable()?;
+            if value == 0 {
+                return Err(Error::NothingVested);
+            }
+            self.ensure_accepted()?;
+            self.deposit -= value;
+            if let Some(vesting) = self.vesting.as_mut() {
+                vesting.released += value;
+            }
+
+            if self.deposit == 0 {
+                self.settle(State::Released, self.seller);
+            }
+            self.pay_seller(value);
+            Ok(())
+        }
+
+        /// The buyer cancels future periods of a subscription, or vesting. Contract transfers
+        /// the part of the deposit for periods elapsed, or vested, so far to the seller and refunds
+        /// the rest. The deal is considered refunded if the seller has not been paid anything under
+        /// the schedule, including any earlier claims or withdrawals
+        #[ink(message)]
+        pub fn cancel(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer {
+                return Err(Error::CancelNotAsBuyer);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            if self.state == State::Disputed {
+                return Err(Error::Disputed);
+            }
+            let seller_share = match self.subscription {
+                Some(subscription) => {
+                    let remaining = subscription.count - subscription.claimed;
+                    let unclaimed = self.elapsed_periods(&subscription) - subscription.claimed;
+                    self.deposit
+                        .checked_mul(unclaimed as Balance)
+                        .ok_or(Error::Overflow)?
+                        / remaining as Balance
+                }
+                None => self.releasable()?,
+            };
+            if seller_share > 0 {
+                self.ensure_accepted()?;
+            }
+            let buyer_share = self.deposit - seller_share;
+            let paid_before = self
+                .subscription
+                .map_or(false, |subscription| subscription.claimed > 0)
+                || self.vesting.map_or(false, |vesting| vesting.released > 0);
+            let state = if seller_share == 0 && !paid_before {
+                State::Refunded
+            } else {
+                State::Released
+            };
+            self.settle(state, self.seller);
+            if seller_share > 0 {
+                self.pay_seller(seller_share);
+            }
+            self.refund_contributors(buyer_share);
+            Ok(())
+        }
+
+        /// The seller claims a deposit locked with a hash by revealing the secret before the timeout.
+        /// Contract transfers a deposit to the seller and publishes the secret
+        #[ink(message)]
+        pub fn claim(&mut self, secret: Vec<u8>) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.seller {
+                return Err(Error::ClaimNotAsSeller);
+            }
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            let matches = match self.hashlock.ok_or(Error::NoHashlock)? {
+                Hashlock::Sha2x256(hash) => self.env().hash_bytes::<Sha2x256>(&secret) == hash,
+                Hashlock::Blake2x256(hash) => self.env().hash_bytes::<Blake2x256>(&secret) == hash,
+            };
+            if !matches {
+                return Err(Error::InvalidSecret);
+            }
+            if self.has_passed(self.delivery_deadline) {
+                return Err(Error::HashlockExpired);
+            }
+            self.env().emit_event(SecretRevealed { secret });
+            self.release_deposit()
+        }
+
+        /// Either party raises a dispute, which then can be resolved by the arbiter
+        #[ink(message)]
+        pub fn raise_dispute(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer && caller != self.seller {
+                return Err(Error::DisputeNotAsParty);
+            }
+            if self.arbiter.is_none() {
+                return Err(Error::NoArbiter);
+            }
+            match self.state {
+                State::Created => return Err(Error::NotAccepted),
+                State::Funded => {}
+                State::Disputed => return Err(Error::AlreadyDisputed),
+                State::Released | State::Refunded => return Err(Error::AlreadySettled),
+            }
+            self.state = State::Disputed;
+            self.env().emit_event(Disputed {
+                escrow: self.env().account_id(),
+                buyer: self.buyer,
+                seller: self.seller,
+                by: caller,
+            });
+            Ok(())
+        }
+
+        /// The arbiter resolves a dispute. Contract splits a deposit between the buyer and the seller
+        /// according to given shares, which must sum up to the deposit
+        #[ink(message)]
+        pub fn resolve(&mut self, buyer_share: Balance, seller_share: Balance) -> Result<()> {
+            let caller = Self::env().caller();
+            if Some(caller) != self.arbiter {
+                return Err(Error::ResolveNotAsArbiter);
+            }
+            if self.state != State::Disputed {
+                return Err(Error::NotDisputed);
+            }
+            self.ensure_not_paused()?;
+            self.ensure_can_split(buyer_share, seller_share)?;
+            self.env().emit_event(Resolved {
+                escrow: self.env().account_id(),
+                buyer: self.buyer,
+                seller: self.seller,
+                buyer_share,
+                seller_share,
+            });
+            // collateral is forfeited only if the seller loses the dispute completely
+            let collateral_to = if seller_share == 0 {
+                self.buyer
+            } else {
+                self.seller
+            };
+            self.split(buyer_share, seller_share, collateral_to)
+        }
+
+        /// Either party proposes to settle the deal with a given split of the deposit. The proposal
+        /// can be accepted by the other party up to `expires_at` block. Any previous proposal
+        /// is replaced
+        #[ink(message)]
+        pub fn propose_settlement(
+            &mut self,
+            buyer_share: Balance,
+            seller_share: Balance,
+            expires_at: BlockNumber,
+        ) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer && caller != self.seller {
+                return Err(Error::ProposeNotAsParty);
+            }
+            if expires_at < self.env().block_number() {
+                return Err(Error::InvalidExpiry);
+            }
+            self.propose(caller, buyer_share, seller_share, expires_at)
+        }
+
+        /// The buyer confirms delivery of damaged goods, proposing to release only `amount` to
+        /// the seller and refund the rest. The seller can accept the proposal with
+        /// `accept_settlement()`, which never expires, or raise a dispute instead
+        #[ink(message)]
+        pub fn confirm_partial(&mut self, amount: Balance) -> Result<()> {
+            let caller = Self::env().caller();
+            if caller != self.buyer {
+                return Err(Error::ConfirmNotAsBuyer);
+            }
+            let buyer_share = self
+                .deposit
+                .checked_sub(amount)
+                .ok_or(Error::InvalidShares)?;
+            if amount > 0 && !self.is_approved() {
+                return Err(Error::ApprovalsPending);
+            }
+            self.propose(caller, buyer_share, amount, BlockNumber::MAX)
+        }
+
+        /// The counterparty of the proposer accepts a pending settlement proposal. Contract splits
+        /// a deposit accordingly
+        #[ink(message)]
+        pub fn accept_settlement(&mut self) -> Result<()> {
+            let caller = Self::env().caller();
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            let proposal = self.proposal.clone().ok_or(Error::NoProposal)?;
+            if caller == proposal.proposer || (caller != self.buyer && caller != self.seller) {
+                return Err(Error::AcceptNotAsCounterparty);
+            }
+            if self.has_passed(Some(proposal.expires_at)) {
+                return Err(Error::ProposalExpired);
+            }
+            if proposal.seller_share > 0 && !self.is_approved() {
+                return Err(Error::ApprovalsPending);
+            }
+            self.ensure_can_split(proposal.buyer_share, proposal.seller_share)?;
+            self.env().emit_event(SettlementAccepted {
+                proposer: proposal.proposer,
+                buyer_share: proposal.buyer_share,
+                seller_share: proposal.seller_share,
+            });
+            self.split(proposal.buyer_share, proposal.seller_share, self.seller)
+        }
+
+        /// The buyer closes a settled deal. Contract terminates itself, transferring any remaining
+        /// funds to the buyer
+        #[ink(message)]
+        pub fn close(&mut self) -> Result<()> {
+            if Self::env().caller() != self.buyer {
+                return Err(Error::CloseNotAsBuyer);
+            }
+            if !self.is_settled() {
+                return Err(Error::NotSettled);
+            }
+            self.ensure_not_paused()?;
+            self.env().emit_event(Terminated {
+                escrow: self.env().account_id(),
+                buyer: self.buyer,
+                seller: self.seller,
+            });
+            self.env().terminate_contract(self.buyer)
+        }
+
+        fn confirm_as(&mut self, contributor: AccountId) -> Result<()> {
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            self.ensure_accepted()?;
+            if self.confirmations.contains(&contributor) {
+                return Err(Error::AlreadyConfirmed);
+            }
+            self.confirmations.push(contributor);
+            self.env().emit_event(ConfirmationAdded { by: contributor });
+            if (self.confirmations.len() as u32) < self.confirmation_threshold
+                || !self.is_approved()
+            {
+                return Ok(());
+            }
+            self.release_deposit()
+        }
+
+        fn do_refund(&mut self) -> Result<()> {
+            self.ensure_not_settled()?;
+            self.ensure_not_paused()?;
+            self.refund_deposit(self.seller)
+        }
+
+        fn add_contribution(&mut self, contributor: AccountId, value: Balance) {
+            let contribution = match self.contributions.get(contributor) {
+                Some(contribution) => contribution,
+                None => {
+                    self.contributors.push(contributor);
+                    0
+                }
+            };
+            self.contributions
+                .insert(contributor, &(contribution + value));
+            self.deposit += value;
+            self.env().emit_event(Transfer {
+                to: self.env().account_id(),
+                value,
+            });
+        }
+
+        /// Refunds `value` to contributors, pro rata to their contributions. Rounding
+        /// remainder goes to the last contributor. Traps on failure
+        fn refund_contributors(&mut self, value: Balance) {
+            let contributors = self.contributors.clone();
+            let total: Balance = contributors
+                .iter()
+                .map(|contributor| self.get_contribution(*contributor))
+                .sum();
+            let mut remaining = value;
+            for (i, contributor) in contributors.iter().enumerate() {
+                let share = if i == contributors.len() - 1 {
+                    remaining
+                } else {
+                    value
+                        .checked_mul(self.get_contribution(*contributor))
+                        .unwrap_or_else(|| Self::trap(Error::Overflow))
+                        / total
+                };
+                remaining -= share;
+                self.make_transfer(*contributor, share);
+            }
+        }
+
+        /// Checks that `action` was signed by `signer` with the current nonce, and bumps the nonce
+        fn verify_signature(
+            &mut self,
+            signer: AccountId,
+            action: SignedAction,
+            signature: &[u8; 65],
+        ) -> Result<()> {
+            let message_hash = self.env().hash_encoded::<Blake2x256, _>(&(
+                self.env().account_id(),
+                action,
+                self.nonce,
+            ));
+            let public_key = self
+                .env()
+                .ecdsa_recover(signature, &message_hash)
+                .map_err(|_| Error::InvalidSignature)?;
+            let account = AccountId::from(self.env().hash_bytes::<Blake2x256>(&public_key));
+            if account != signer {
+                return Err(Error::InvalidSignature);
+            }
+            self.nonce += 1;
+            Ok(())
+        }
+
+        fn propose(
+            &mut self,
+            proposer: AccountId,
+            buyer_share: Balance,
+            seller_share: Balance,
+            expires_at: BlockNumber,
+        ) -> Result<()> {
+            self.ensure_not_settled()?;
+            if buyer_share.checked_add(seller_share) != Some(self.deposit) {
+                return Err(Error::InvalidShares);
+            }
+            self.proposal = Some(Proposal {
+                proposer,
+                buyer_share,
+                seller_share,
+                expires_at,
+            });
+            self.env().emit_event(SettlementProposed {
+                proposer,
+                buyer_share,
+                seller_share,
+                expires_at,
+            });
+            Ok(())
+        }
+
+        /// Settles the deal by releasing the whole deposit to the seller
+        fn release_deposit(&mut self) -> Result<()> {
+            self.ensure_accepted()?;
+            let value = self.deposit;
+            self.settle(State::Released, self.seller);
+            self.pay_seller(value);
+            Ok(())
+        }
+
+        /// Settles the deal by refunding the whole deposit to the contributors
+        fn refund_deposit(&mut self, collateral_to: AccountId) -> Result<()> {
+            let value = self.deposit;
+            self.settle(State::Refunded, collateral_to);
+            self.refund_contributors(value);
+            Ok(())
+        }
+
+        /// Checks that the deposit can be split according to given shares
+        fn ensure_can_split(&self, buyer_share: Balance, seller_share: Balance) -> Result<()> {
+            if buyer_share.checked_add(seller_share) != Some(self.deposit) {
+                return Err(Error::InvalidShares);
+            }
+            self.ensure_accepted()
+        }
+
+        /// Settles the deal by splitting the deposit between the contributors and the seller.
+        /// The deal is considered refunded if the seller gets nothing
+        fn split(
+            &mut self,
+            buyer_share: Balance,
+            seller_share: Balance,
+            collateral_to: AccountId,
+        ) -> Result<()> {
+            self.ensure_can_split(buyer_share, seller_share)?;
+            let state = if seller_share == 0 {
+                State::Refunded
+            } else {
+                State::Released
+            };
+            self.settle(state, collateral_to);
+            self.refund_contributors(buyer_share);
+            self.pay_seller(seller_share);
+            Ok(())
+        }
+
+        /// Transfers a payout to the seller, deducting platform fee if there is one, and splits it
+        /// among the payees. Shares waived by payees are refunded to the contributors instead.
+        /// Traps on failure
+        fn pay_seller(&mut self, value: Balance) {
+            let waived_bps = self
+                .payees
+                .iter()
+                .filter(|payee| payee.waived)
+                .map(|payee| payee.share_bps)
+                .sum();
+            let waived_value = Self::portion(value, waived_bps);
+            if waived_value > 0 {
+                self.refund_contributors(waived_value);
+            }
+            let mut payout = value - waived_value;
+
+            if let Some(fee) = self.fee {
+                let fee_value = Self::portion(payout, fee.basis_points);
+                self.make_transfer(fee.collector, fee_value);
+                self.env().emit_event(FeePaid {
+                    collector: fee.collector,
+                    value: fee_value,
+                });
+                payout -= fee_value;
+            }
+
+            let payees: Vec<Payee> = self
+                .payees
+                .iter()
+                .filter(|payee| !payee.waived)
+                .copied()
+                .collect();
+            let active_bps: Balance = payees.iter().map(|payee| payee.share_bps as Balance).sum();
+            let mut remaining = payout;
+            // the lead payee is paid last, taking the rounding remainder
+            for (i, payee) in payees.iter().enumerate().rev() {
+                let share = if i == 0 {
+                    remaining
+                } else {
+                    payout
+                        .checked_mul(payee.share_bps as Balance)
+                        .unwrap_or_else(|| Self::trap(Error::Overflow))
+                        / active_bps
+                };
+                remaining -= share;
+                self.make_transfer(payee.account, share);
+            }
+        }
+
+        /// Returns part of `value` given in basis points. Traps on overflow
+        fn portion(value: Balance, basis_points: u16) -> Balance {
+            value
+                .checked_mul(basis_points as Balance)
+                .unwrap_or_else(|| Self::trap(Error::Overflow))
+                / MAX_BASIS_POINTS as Balance
+        }
+
+        /// Aborts the call, reverting all its changes. Transfers made once the deal is settled
+        /// trap on failure, since returning an error would commit the settlement without them
+        fn trap(error: Error) -> ! {
+            panic!("Settlement failed: {:?}", error)
+        }
+
+        /// Moves the deal to a final state, then returns seller's collateral, or forfeits it to
+        /// the buyer. PSP34 token, if deposited, is transferred to the buyer if the deal is released,
+        /// and returned to the seller otherwise.
+        /// The deposit is zeroed before any transfer, so callers must take its value beforehand
+        /// and pay it out afterwards - a receiver re-entering the contract finds the deal settled.
+        /// Traps on failure
+        fn settle(&mut self, state: State, collateral_to: AccountId) {
+            let accepted = self.state != State::Created;
+            self.deposit = 0;
+            self.state = state;
+
+            let (escrow, buyer, seller) = (self.env().account_id(), self.buyer, self.seller);
+            if state == State::Released {
+                self.env().emit_event(Confirmed {
+                    escrow,
+                    buyer,
+                    seller,
+                });
+            } else {
+                self.env().emit_event(Refunded {
+                    escrow,
+                    buyer,
+                    seller,
+                });
+            }
+
+            if accepted && self.collateral > 0 {
+                self.make_transfer(collateral_to, self.collateral);
+            }
+            if let (true, Some(nft)) = (accepted, self.nft.clone()) {
+                let to = if state == State::Released {
+                    self.buyer
+                } else {
+                    self.seller
+                };
+                if let Err(error) = self.nft_transfer(&nft, to) {
+                    Self::trap(error);
+                }
+            }
+        }
+
+        /// Returns whether `account` is paid out of the deposit, i.e. it is a payee or the fee
+        /// collector
+        fn is_beneficiary(&self, account: AccountId) -> bool {
+            self.payees.iter().any(|payee| payee.account == account)
+                || self.fee.map(|fee| fee.collector) == Some(account)
+        }
+
+        /// Returns whether the threshold of approvals is reached, which is always the case if
+        /// there are no approvers
+        fn is_approved(&self) -> bool {
+            self.approvals.len() as u32 >= self.approval_threshold
+        }
+
+        fn ensure_accepted(&self) -> Result<()> {
+            if self.state == State::Created {
+                return Err(Error::NotAccepted);
+            }
+            Ok(())
+        }
+
+        fn is_settled(&self) -> bool {
+            matches!(self.state, State::Released | State::Refunded)
+        }
+
+        fn ensure_not_settled(&self) -> Result<()> {
+            if self.is_settled() {
+                return Err(Error::AlreadySettled);
+            }
+            Ok(())
+        }
+
+        fn ensure_not_paused(&self) -> Result<()> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+            Ok(())
+        }
+
+        fn nft_transfer(&self, nft: &Nft, to: AccountId) -> Result<()> {
+            build_call::<ink_env::DefaultEnvironment>()
+                .call_type(Call::new().callee(nft.collection))
+                .call_flags(CallFlags::default().set_allow_reentry(false))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(PSP34_TRANSFER_SELECTOR))
+                        .push_arg(to)
+                        .push_arg(nft.id.clone())
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<core::result::Result<(), PSP34Error>>()
+                .fire()
+                .map_err(|_| Error::NftCallFailed)?
+                .map_err(Error::NftTransferRejected)
+        }
+
+        fn elapsed_periods(&self, subscription: &Subscription) -> u32 {
+            if self.state == State::Created {
+                return 0;
+            }
+            let elapsed = (self.env().block_number() - subscription.start) / subscription.period;
+            elapsed.min(subscription.count)
+        }
+
+        fn has_passed(&self, deadline: Option<BlockNumber>) -> bool {
+            deadline.map_or(false, |deadline| self.env().block_number() > deadline)
+        }
+
+        /// Transfers `value` to `to`. Traps on failure
+        fn make_transfer(&mut self, to: AccountId, value: Balance) {
+            #[cfg(test)]
+            tests::on_transfer(self, to);
+            if self.env().transfer(to, value).is_err() {
+                Self::trap(Error::TransferFailed);
+            }
+            self.env().emit_event(Transfer { to, value });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_env::AccountId;
+
+        /// Imports `ink_lang` so we can use `#[ink::test]`.
+        use ink_lang as ink;
+
+        use std::cell::{Cell, RefCell};
+
+        thread_local! {
+            /// State of the deal and its deposit as seen by receivers of transfers, along with
+            /// the outcome of calling back into the contract, if they did
+            static OBSERVED: RefCell<Vec<(AccountId, State, Balance, Option<Result<()>>)>> =
+                RefCell::new(Vec::new());
+            /// Whether the next receiver calls back into the contract
+            static REENTER: Cell<bool> = Cell::new(false);
+        }
+
+        /// Stands for a malicious receiver, called at the moment it receives funds. It inspects
+        /// the contract and, if asked to, calls back into it trying to get the deposit again -
+        /// the buyer reclaims it, anyone else collects it
+        pub(super) fn on_transfer(escrow: &mut Escrow, to: AccountId) {
+            let (state, deposit) = (escrow.state, escrow.deposit);
+            let reentry = REENTER.with(|reenter| reenter.replace(false)).then(|| {
+                let caller = ink_env::caller::<ink_env::DefaultEnvironment>();
+                set_caller(to);
+                let result = if to == escrow.buyer {
+                    escrow.reclaim()
+                } else {
+                    escrow.collect()
+                };
+                set_caller(caller);
+                result
+            });
+            OBSERVED.with(|observed| observed.borrow_mut().push((to, state, deposit, reentry)));
+        }
+
+        fn reenter_on_next_transfer() {
+            REENTER.with(|reenter| reenter.set(true));
+        }
+
+        /// Takes what the receiver observed so far
+        fn observed_by(receiver: AccountId) -> Vec<(State, Balance, Option<Result<()>>)> {
+            OBSERVED.with(|observed| {
+                observed
+                    .take()
+                    .into_iter()
+                    .filter(|(to, ..)| *to == receiver)
+                    .map(|(_, state, deposit, reentry)| (state, deposit, reentry))
+                    .collect()
+            })
+        }
+
+        fn get_default_test_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment>
+        {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+        }
+
+        fn set_balance(account_id: AccountId, balance: Balance) {
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
+        }
+
+        fn get_balance(account_id: AccountId) -> Balance {
+            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account_id)
+                .expect("Cannot get account balance")
+        }
+
+        fn contract_id() -> AccountId {
+            ink_env::test::callee::<ink_env::DefaultEnvironment>()
+        }
+
+        fn recorded_events() -> Vec<Event> {
+            ink_env::test::recorded_events()
+                .map(|event| {
+                    <Event as scale::Decode>::decode(&mut &event.data[..])
+                        .expect("Invalid event data")
+                })
+                .collect()
+        }
+
+        fn set_caller(caller: AccountId) {
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+        }
+
+        fn create_contract(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let accounts = get_default_test_accounts();
+            let buyer = accounts.eve;
+            let seller = accounts.frank;
+
+            set_caller(buyer);
+            set_balance(contract_id(), deposit);
+            let escrow = Escrow::new(vec![(seller, MAX_BASIS_POINTS)], Terms::default());
+
+            (escrow, buyer, seller)
+        }
+
+        fn create_funded_contract(
+            deposit: Balance,
+            terms: Terms,
+        ) -> (Escrow, AccountId, AccountId) {
+            let accounts = get_default_test_accounts();
+            let buyer = accounts.eve;
+            let seller = accounts.frank;
+
+            set_caller(buyer);
+            set_balance(contract_id(), deposit);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(deposit);
+            let escrow = Escrow::new(vec![(seller, MAX_BASIS_POINTS)], terms);
+
+            (escrow, buyer, seller)
+        }
+
+        fn create_contract_with_arbiter(
+            deposit: Balance,
+        ) -> (Escrow, AccountId, AccountId, AccountId) {
+            let arbiter = get_default_test_accounts().django;
+            let terms = Terms {
+                arbiter: Some(arbiter),
+                ..Default::default()
+            };
+            let (escrow, buyer, seller) = create_funded_contract(deposit, terms);
+
+            (escrow, buyer, seller, arbiter)
+        }
+
+        fn create_contract_with_deadlines(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                arbiter: Some(get_default_test_accounts().django),
+                delivery_deadline: Some(now + 2),
+                confirmation_deadline: Some(now + 4),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        fn create_contract_with_milestones(amounts: &[Balance]) -> (Escrow, AccountId, AccountId) {
+            let terms = Terms {
+                milestones: amounts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, amount)| (*amount, Hash::from([i as u8; 32])))
+                    .collect(),
+                ..Default::default()
+            };
+            create_funded_contract(amounts.iter().sum(), terms)
+        }
+
+        fn create_contract_with_hashlock(
+            deposit: Balance,
+            secret: &[u8],
+        ) -> (Escrow, AccountId, AccountId) {
+            let mut hash = [0u8; 32];
+            ink_env::hash_bytes::<Sha2x256>(secret, &mut hash);
+            let terms = Terms {
+                delivery_deadline: Some(ink_env::block_number::<ink_env::DefaultEnvironment>() + 2),
+                hashlock: Some(Hashlock::Sha2x256(hash)),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        fn create_contract_with_fee(
+            deposit: Balance,
+            basis_points: u16,
+        ) -> (Escrow, AccountId, AccountId, AccountId) {
+            let collector = get_default_test_accounts().django;
+            let terms = Terms {
+                fee: Some(Fee {
+                    collector,
+                    basis_points,
+                }),
+                ..Default::default()
+            };
+            let (escrow, buyer, seller) = create_funded_contract(deposit, terms);
+
+            (escrow, buyer, seller, collector)
+        }
+
+        fn create_contract_with_collateral(
+            deposit: Balance,
+            collateral: Balance,
+        ) -> (Escrow, AccountId, AccountId) {
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                arbiter: Some(get_default_test_accounts().django),
+                delivery_deadline: Some(now + 2),
+                collateral,
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        fn accept(escrow: &mut Escrow, seller: AccountId, collateral: Balance) {
+            set_caller(seller);
+            transfer_in(collateral);
+            escrow.accept().expect("Accept failed!")
+        }
+
+        fn transfer_in(value: Balance) {
+            set_balance(contract_id(), get_balance(contract_id()) + value);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(value);
+        }
+
+        fn advance_blocks(count: u32) {
+            for _ in 0..count {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+        }
+
+        fn confirm(escrow: &mut Escrow, buyer: AccountId) -> () {
+            set_caller(buyer);
+            escrow.confirm().expect("Confirm failed!")
+        }
+
+        fn refund(escrow: &mut Escrow, seller: AccountId) -> () {
+            set_caller(seller);
+            escrow.refund().expect("Refund failed!")
+        }
+
+        fn close(mut escrow: Escrow, buyer: AccountId) {
+            // stands for the storage deposit and any funds sent to the contract directly
+            const RESIDUE: Balance = 7;
+            set_balance(contract_id(), get_balance(contract_id()) + RESIDUE);
+
+            let should_terminate = move || {
+                set_caller(buyer);
+                escrow.close().expect("Close failed!")
+            };
+            ink_env::test::assert_contract_termination::<ink_env::DefaultEnvironment, _>(
+                should_terminate,
+                buyer,
+                RESIDUE,
+            );
+        }
+
+        #[ink::test]
+        fn when_constructor_is_called_then_contract_has_deposit() {
+            const DEPOSIT: Balance = 123;
+            let (escrow, buyer, seller) = create_contract(DEPOSIT);
+            assert_eq!(escrow.buyer, buyer);
+            assert_eq!(escrow.seller, seller);
+            assert_eq!(get_balance(contract_id()), DEPOSIT);
+        }
+
+        #[ink::test]
+        fn when_buyer_confirms_then_seller_receives_deposit() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller) = create_funded_contract(DEPOSIT, Terms::default());
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(escrow.get_deposit(), 0);
+            assert_eq!(get_balance(contract_id()), 0);
+            assert_eq!(get_balance(seller), DEPOSIT);
+            assert_eq!(get_balance(buyer), 0);
+        }
+
+        #[ink::test]
+        fn when_not_buyer_confirms_then_error_is_returned() {
+            let (mut escrow, _, seller) = create_contract(0);
+            set_caller(seller);
+            assert_eq!(escrow.confirm(), Err(Error::ConfirmNotAsBuyer));
+        }
+
+        #[ink::test]
+        fn when_seller_refunds_then_deposit_is_returned_to_buyer() {
+            const DEPOSIT: Balance = 1123;
+            let (mut escrow, buyer, seller) = create_funded_contract(DEPOSIT, Terms::default());
+
+            refund(&mut escrow, seller);
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(escrow.get_deposit(), 0);
+            assert_eq!(get_balance(contract_id()), 0);
+            assert_eq!(get_balance(seller), 0);
+            assert_eq!(get_balance(buyer), DEPOSIT);
+        }
+
+        #[ink::test]
+        fn when_not_seller_refunds_then_error_is_returned() {
+            let (mut escrow, buyer, _) = create_contract(0);
+            set_caller(buyer);
+            assert_eq!(escrow.refund(), Err(Error::RefundNotAsSeller));
+        }
+
+        #[ink::test]
+        fn when_no_arbiter_then_dispute_cannot_be_raised() {
+            let (mut escrow, buyer, _) = create_contract(0);
+            set_caller(buyer);
+            assert_eq!(escrow.raise_dispute(), Err(Error::NoArbiter));
+        }
+
+        #[ink::test]
+        fn when_not_party_raises_dispute_then_error_is_returned() {
+            let (mut escrow, _, _, arbiter) = create_contract_with_arbiter(0);
+            set_caller(arbiter);
+            assert_eq!(escrow.raise_dispute(), Err(Error::DisputeNotAsParty));
+        }
+
+        #[ink::test]
+        fn when_dispute_is_raised_twice_then_error_is_returned() {
+            let (mut escrow, buyer, seller, _) = create_contract_with_arbiter(0);
+            set_caller(buyer);
+            assert_eq!(escrow.raise_dispute(), Ok(()));
+            assert_eq!(escrow.get_state(), State::Disputed);
+            set_caller(seller);
+            assert_eq!(escrow.raise_dispute(), Err(Error::AlreadyDisputed));
+        }
+
+        #[ink::test]
+        fn when_not_arbiter_resolves_then_error_is_returned() {
+            let (mut escrow, buyer, seller, _) = create_contract_with_arbiter(0);
+            set_caller(seller);
+            escrow.raise_dispute().expect("Raising dispute failed!");
+            set_caller(buyer);
+            assert_eq!(escrow.resolve(0, 0), Err(Error::ResolveNotAsArbiter));
+        }
+
+        #[ink::test]
+        fn when_no_dispute_then_arbiter_cannot_resolve() {
+            let (mut escrow, _, _, arbiter) = create_contract_with_arbiter(0);
+            set_caller(arbiter);
+            assert_eq!(escrow.resolve(0, 0), Err(Error::NotDisputed));
+        }
+
+        #[ink::test]
+        fn when_shares_do_not_sum_up_to_deposit_then_error_is_returned() {
+            let (mut escrow, buyer, _, arbiter) = create_contract_with_arbiter(10);
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raising dispute failed!");
+            set_caller(arbiter);
+            assert_eq!(escrow.resolve(5, 6), Err(Error::InvalidShares));
+            assert_eq!(escrow.resolve(Balance::MAX, 11), Err(Error::InvalidShares));
+            assert_eq!(escrow.get_state(), State::Disputed);
+            assert!(!recorded_events()
+                .iter()
+                .any(|event| matches!(event, Event::Resolved(_))));
+        }
+
+        #[ink::test]
+        fn when_arbiter_resolves_then_deposit_is_split() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller, arbiter) = create_contract_with_arbiter(DEPOSIT);
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raising dispute failed!");
+
+            set_caller(arbiter);
+            escrow.resolve(3, 7).expect("Resolve failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(contract_id()), 0);
+            assert_eq!(get_balance(buyer), 3);
+            assert_eq!(get_balance(seller), 7);
+        }
+
+        #[ink::test]
+        fn when_delivery_deadline_has_not_passed_then_buyer_cannot_reclaim() {
+            let (mut escrow, buyer, _) = create_contract_with_deadlines(10);
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::DeliveryDeadlineNotPassed));
+            advance_blocks(2);
+            assert_eq!(escrow.reclaim(), Err(Error::DeliveryDeadlineNotPassed));
+        }
+
+        #[ink::test]
+        fn when_no_deadlines_then_deposit_cannot_be_reclaimed_nor_collected() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            advance_blocks(100);
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::DeliveryDeadlineNotPassed));
+            set_caller(seller);
+            assert_eq!(escrow.collect(), Err(Error::ConfirmationDeadlineNotPassed));
+        }
+
+        #[ink::test]
+        fn when_not_buyer_reclaims_then_error_is_returned() {
+            let (mut escrow, _, seller) = create_contract_with_deadlines(10);
+            advance_blocks(3);
+            set_caller(seller);
+            assert_eq!(escrow.reclaim(), Err(Error::ReclaimNotAsBuyer));
+        }
+
+        #[ink::test]
+        fn when_delivery_deadline_has_passed_then_buyer_reclaims_deposit() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller) = create_contract_with_deadlines(DEPOSIT);
+            advance_blocks(3);
+
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), DEPOSIT);
+            assert_eq!(get_balance(seller), 0);
+        }
+
+        #[ink::test]
+        fn when_confirmation_deadline_has_not_passed_then_seller_cannot_collect() {
+            let (mut escrow, buyer, seller) = create_contract_with_deadlines(10);
+            advance_blocks(4);
+            set_caller(buyer);
+            assert_eq!(escrow.collect(), Err(Error::CollectNotAsSeller));
+            set_caller(seller);
+            assert_eq!(escrow.collect(), Err(Error::ConfirmationDeadlineNotPassed));
+        }
+
+        #[ink::test]
+        fn when_confirmation_deadline_has_passed_then_seller_collects_deposit() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller) = create_contract_with_deadlines(DEPOSIT);
+            advance_blocks(5);
+
+            set_caller(seller);
+            escrow.collect().expect("Collect failed!");
+            assert_eq!(get_balance(buyer), 0);
+            assert_eq!(get_balance(seller), DEPOSIT);
+        }
+
+        #[ink::test]
+        fn when_disputed_then_deadlines_do_not_apply() {
+            let (mut escrow, buyer, seller) = create_contract_with_deadlines(10);
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raising dispute failed!");
+            advance_blocks(5);
+            assert_eq!(escrow.reclaim(), Err(Error::Disputed));
+            set_caller(seller);
+            assert_eq!(escrow.collect(), Err(Error::Disputed));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Delivery deadline must precede confirmation deadline")]
+        fn when_deadlines_are_out_of_order_then_constructor_panics() {
+            let terms = Terms {
+                delivery_deadline: Some(5),
+                confirmation_deadline: Some(5),
+                ..Default::default()
+            };
+            create_funded_contract(0, terms);
+        }
+
+        #[ink::test]
+        fn when_buyer_confirms_milestone_then_seller_receives_its_amount() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[4, 6]);
+            set_caller(buyer);
+            assert_eq!(escrow.confirm_milestone(0), Ok(()));
+
+            assert_eq!(escrow.get_deposit(), 6);
+            assert!(escrow.get_milestones()[0].released);
+            assert!(!escrow.get_milestones()[1].released);
+            assert_eq!(get_balance(seller), 4);
+            assert_eq!(get_balance(contract_id()), 6);
+        }
+
+        #[ink::test]
+        fn when_milestone_is_invalid_then_error_is_returned() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[4, 6]);
+            set_caller(seller);
+            assert_eq!(escrow.confirm_milestone(0), Err(Error::ConfirmNotAsBuyer));
+            set_caller(buyer);
+            assert_eq!(escrow.confirm_milestone(2), Err(Error::MilestoneNotFound));
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
+            assert_eq!(
+                escrow.confirm_milestone(0),
+                Err(Error::MilestoneAlreadyReleased)
+            );
+        }
+
+        #[ink::test]
+        fn when_last_milestone_is_confirmed_then_deal_is_released() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[4, 6]);
+            set_caller(buyer);
+            escrow
+                .confirm_milestone(1)
+                .expect("Confirm milestone failed!");
+            assert_eq!(escrow.get_state(), State::Funded);
+
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
+        }
+
+        #[ink::test]
+        fn when_seller_refunds_then_remainder_after_milestones_is_returned() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[4, 6]);
+            set_caller(buyer);
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
+
+            refund(&mut escrow, seller);
+            assert_eq!(get_balance(buyer), 6);
+            assert_eq!(get_balance(seller), 4);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Milestones must sum up to the deposit")]
+        fn when_milestones_do_not_sum_up_to_deposit_then_constructor_panics() {
+            let terms = Terms {
+                milestones: vec![(4, Hash::from([0; 32]))],
+                ..Default::default()
+            };
+            create_funded_contract(5, terms);
+        }
+
+        #[ink::test]
+        fn when_buyer_is_given_in_terms_then_it_is_not_the_instantiator() {
+            let django = get_default_test_accounts().django;
+            let terms = Terms {
+                buyer: Some(django),
+                ..Default::default()
+            };
+            let (escrow, instantiator, _) = create_funded_contract(0, terms);
+            assert_eq!(escrow.get_buyer(), django);
+            assert_ne!(escrow.get_buyer(), instantiator);
+        }
+
+        #[ink::test]
+        fn when_counterparty_accepts_proposal_then_deposit_is_split() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller) = create_funded_contract(DEPOSIT, Terms::default());
+            set_caller(seller);
+            assert_eq!(escrow.propose_settlement(4, 6, 10), Ok(()));
+            assert_eq!(
+                escrow.get_proposal(),
+                Some(Proposal {
+                    proposer: seller,
+                    buyer_share: 4,
+                    seller_share: 6,
+                    expires_at: 10,
+                })
+            );
+
+            set_caller(buyer);
+            escrow.accept_settlement().expect("Accept failed!");
+            assert_eq!(get_balance(buyer), 4);
+            assert_eq!(get_balance(seller), 6);
+        }
+
+        #[ink::test]
+        fn when_proposal_is_invalid_then_error_is_returned() {
+            let (mut escrow, buyer, _) = create_funded_contract(10, Terms::default());
+            set_caller(get_default_test_accounts().django);
+            assert_eq!(
+                escrow.propose_settlement(4, 6, 10),
+                Err(Error::ProposeNotAsParty)
+            );
+            set_caller(buyer);
+            assert_eq!(
+                escrow.propose_settlement(4, 7, 10),
+                Err(Error::InvalidShares)
+            );
+            advance_blocks(2);
+            assert_eq!(
+                escrow.propose_settlement(4, 6, 1),
+                Err(Error::InvalidExpiry)
+            );
+            assert_eq!(escrow.get_proposal(), None);
+        }
+
+        #[ink::test]
+        fn when_not_counterparty_accepts_proposal_then_error_is_returned() {
+            let (mut escrow, buyer, _) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            assert_eq!(escrow.accept_settlement(), Err(Error::NoProposal));
+            escrow
+                .propose_settlement(4, 6, 10)
+                .expect("Propose failed!");
+            assert_eq!(
+                escrow.accept_settlement(),
+                Err(Error::AcceptNotAsCounterparty)
+            );
+            set_caller(get_default_test_accounts().django);
+            assert_eq!(
+                escrow.accept_settlement(),
+                Err(Error::AcceptNotAsCounterparty)
+            );
+        }
+
+        #[ink::test]
+        fn when_deposit_is_topped_up_then_outdated_proposal_cannot_be_accepted() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(seller);
+            escrow
+                .propose_settlement(4, 6, 10)
+                .expect("Propose failed!");
+            set_caller(buyer);
+            transfer_in(5);
+            escrow.top_up().expect("Top up failed!");
+            assert_eq!(escrow.accept_settlement(), Err(Error::InvalidShares));
+            assert_eq!(escrow.get_state(), State::Funded);
+            assert!(!recorded_events()
+                .iter()
+                .any(|event| matches!(event, Event::SettlementAccepted(_))));
+        }
+
+        #[ink::test]
+        fn when_proposal_has_expired_then_it_cannot_be_accepted() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            escrow.propose_settlement(4, 6, 2).expect("Propose failed!");
+            advance_blocks(3);
+            set_caller(seller);
+            assert_eq!(escrow.accept_settlement(), Err(Error::ProposalExpired));
+        }
+
+        #[ink::test]
+        fn when_counterproposal_is_made_then_previous_proposal_is_replaced() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            escrow
+                .propose_settlement(8, 2, 10)
+                .expect("Propose failed!");
+            set_caller(seller);
+            escrow
+                .propose_settlement(5, 5, 10)
+                .expect("Propose failed!");
+            assert_eq!(
+                escrow.accept_settlement(),
+                Err(Error::AcceptNotAsCounterparty)
+            );
+            assert_eq!(
+                escrow.get_proposal().map(|proposal| proposal.proposer),
+                Some(seller)
+            );
+        }
+
+        #[ink::test]
+        fn when_seller_reveals_secret_then_deposit_is_claimed_and_secret_published() {
+            const DEPOSIT: Balance = 10;
+            let (mut escrow, buyer, seller) = create_contract_with_hashlock(DEPOSIT, b"secret");
+
+            set_caller(seller);
+            escrow.claim(b"secret".to_vec()).expect("Claim failed!");
+            assert_eq!(get_balance(seller), DEPOSIT);
+
+            assert!(recorded_events().iter().any(
+                |event| matches!(event, Event::SecretRevealed(SecretRevealed { secret }) if secret == b"secret")
+            ));
+        }
+
+        #[ink::test]
+        fn when_secret_is_invalid_then_error_is_returned() {
+            let (mut escrow, buyer, seller) = create_contract_with_hashlock(10, b"secret");
+            set_caller(buyer);
+            assert_eq!(
+                escrow.claim(b"secret".to_vec()),
+                Err(Error::ClaimNotAsSeller)
+            );
+            set_caller(seller);
+            assert_eq!(escrow.claim(b"guess".to_vec()), Err(Error::InvalidSecret));
+        }
+
+        #[ink::test]
+        fn when_no_hashlock_then_deposit_cannot_be_claimed() {
+            let (mut escrow, _, seller) = create_funded_contract(10, Terms::default());
+            set_caller(seller);
+            assert_eq!(escrow.claim(b"secret".to_vec()), Err(Error::NoHashlock));
+        }
+
+        #[ink::test]
+        fn when_timeout_has_passed_then_seller_cannot_claim() {
+            let (mut escrow, buyer, seller) = create_contract_with_hashlock(10, b"secret");
+            advance_blocks(3);
+            set_caller(seller);
+            assert_eq!(
+                escrow.claim(b"secret".to_vec()),
+                Err(Error::HashlockExpired)
+            );
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Hashlock requires delivery deadline")]
+        fn when_hashlock_has_no_timeout_then_constructor_panics() {
+            let terms = Terms {
+                hashlock: Some(Hashlock::Blake2x256([0; 32])),
+                ..Default::default()
+            };
+            create_funded_contract(0, terms);
+        }
+
+        #[ink::test]
+        fn when_buyer_tops_up_then_deposit_is_increased() {
+            let (mut escrow, buyer, _) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            transfer_in(5);
+            assert_eq!(escrow.top_up(), Ok(()));
+            assert_eq!(escrow.get_deposit(), 15);
+            assert_eq!(
+                escrow.get_balance_report(),
+                BalanceReport {
+                    deposit: 15,
+                    collateral: 0,
+                    balance: 15,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn when_not_buyer_tops_up_then_error_is_returned() {
+            let (mut escrow, _, seller) = create_funded_contract(10, Terms::default());
+            set_caller(seller);
+            transfer_in(5);
+            assert_eq!(escrow.top_up(), Err(Error::TopUpNotAsBuyer));
+        }
+
+        #[ink::test]
+        fn when_funds_are_sent_directly_then_balance_report_shows_difference() {
+            let (escrow, _, _) = create_funded_contract(10, Terms::default());
+            set_balance(contract_id(), 12);
+            assert_eq!(
+                escrow.get_balance_report(),
+                BalanceReport {
+                    deposit: 10,
+                    collateral: 0,
+                    balance: 12,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn when_collateral_is_posted_then_balance_report_includes_it() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            assert_eq!(
+                escrow.get_balance_report(),
+                BalanceReport {
+                    deposit: 10,
+                    collateral: 5,
+                    balance: 15,
+                }
+            );
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(
+                escrow.get_balance_report(),
+                BalanceReport {
+                    deposit: 0,
+                    collateral: 0,
+                    balance: 0,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn when_deposit_is_topped_up_then_last_milestone_releases_the_rest() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[4, 6]);
+            set_caller(buyer);
+            transfer_in(2);
+            escrow.top_up().expect("Top up failed!");
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
+            assert_eq!(get_balance(seller), 4);
+
+            escrow
+                .confirm_milestone(1)
+                .expect("Confirm milestone failed!");
+            assert_eq!(get_balance(seller), 12);
+        }
+
+        #[ink::test]
+        fn when_buyer_confirms_then_fee_is_deducted_from_seller_payout() {
+            let (mut escrow, buyer, seller, collector) = create_contract_with_fee(1000, 250);
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(get_balance(collector), 25);
+            assert_eq!(get_balance(seller), 975);
+            assert_eq!(get_balance(buyer), 0);
+        }
+
+        #[ink::test]
+        fn when_deposit_is_refunded_then_no_fee_is_charged() {
+            let (mut escrow, buyer, seller, collector) = create_contract_with_fee(1000, 250);
+
+            refund(&mut escrow, seller);
+            assert_eq!(get_balance(collector), 0);
+            assert_eq!(get_balance(buyer), 1000);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Settlement failed: Overflow")]
+        fn when_fee_computation_overflows_then_settlement_traps() {
+            let (mut escrow, buyer, _, _) = create_contract_with_fee(Balance::MAX, 2);
+            set_caller(buyer);
+            let _ = escrow.confirm();
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Fee cannot exceed the payout")]
+        fn when_fee_exceeds_payout_then_constructor_panics() {
+            create_contract_with_fee(0, MAX_BASIS_POINTS + 1);
+        }
+
+        #[ink::test]
+        fn when_collateral_is_not_posted_then_deal_is_not_active() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            assert_eq!(escrow.get_state(), State::Created);
+            set_caller(buyer);
+            assert_eq!(escrow.confirm(), Err(Error::NotAccepted));
+            assert_eq!(escrow.raise_dispute(), Err(Error::NotAccepted));
+            set_caller(seller);
+            transfer_in(4);
+            assert_eq!(escrow.accept(), Err(Error::InvalidCollateral));
+            set_caller(buyer);
+            transfer_in(5);
+            assert_eq!(escrow.accept(), Err(Error::AcceptNotAsSeller));
+        }
+
+        #[ink::test]
+        fn when_collateral_is_not_posted_then_buyer_can_reclaim_before_deadline() {
+            let (mut escrow, buyer, _) = create_contract_with_collateral(10, 5);
+
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        fn when_buyer_confirms_then_collateral_is_returned_to_seller() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            assert_eq!(escrow.accept(), Err(Error::AlreadyAccepted));
+            set_balance(seller, 0);
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(get_balance(seller), 15);
+            assert_eq!(get_balance(buyer), 0);
+        }
+
+        #[ink::test]
+        fn when_delivery_deadline_has_passed_then_collateral_is_forfeited() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            set_balance(seller, 0);
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::DeliveryDeadlineNotPassed));
+            advance_blocks(3);
+
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), 15);
+            assert_eq!(get_balance(seller), 0);
+        }
+
+        #[ink::test]
+        fn when_arbiter_rules_against_seller_then_collateral_is_forfeited() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            set_balance(seller, 0);
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raising dispute failed!");
+
+            set_caller(get_default_test_accounts().django);
+            escrow.resolve(10, 0).expect("Resolve failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(buyer), 15);
+            assert_eq!(get_balance(seller), 0);
+        }
+
+        #[ink::test]
+        fn when_deal_is_not_settled_then_it_cannot_be_closed() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            assert_eq!(escrow.close(), Err(Error::NotSettled));
+            confirm(&mut escrow, buyer);
+            set_caller(seller);
+            assert_eq!(escrow.close(), Err(Error::CloseNotAsBuyer));
+        }
+
+        #[ink::test]
+        fn when_deal_is_released_then_buyer_closes_it_and_receives_remaining_funds() {
+            let (mut escrow, buyer, _) = create_funded_contract(10, Terms::default());
+            confirm(&mut escrow, buyer);
+            close(escrow, buyer);
+        }
+
+        #[ink::test]
+        fn when_deal_is_refunded_then_buyer_closes_it_and_receives_remaining_funds() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            refund(&mut escrow, seller);
+            close(escrow, buyer);
+        }
+
+        #[ink::test]
+        fn when_deal_is_settled_then_it_cannot_be_settled_again() {
+            let (mut escrow, buyer, seller) = create_contract_with_deadlines(10);
+            confirm(&mut escrow, buyer);
+            assert_eq!(escrow.confirm(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.top_up(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.raise_dispute(), Err(Error::AlreadySettled));
+            set_caller(seller);
+            assert_eq!(escrow.refund(), Err(Error::AlreadySettled));
+            advance_blocks(5);
+            assert_eq!(escrow.collect(), Err(Error::AlreadySettled));
+            assert_eq!(get_balance(seller), 10);
+        }
+
+        fn create_contract_with_nft(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let terms = Terms {
+                nft: Some(Nft {
+                    collection: get_default_test_accounts().django,
+                    id: Id::U32(7),
+                }),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        #[ink::test]
+        fn when_nft_is_not_deposited_then_deal_is_not_active() {
+            let (mut escrow, buyer, _) = create_contract_with_nft(10);
+            assert_eq!(escrow.get_state(), State::Created);
+            assert_eq!(escrow.get_nft().map(|nft| nft.id), Some(Id::U32(7)));
+            set_caller(buyer);
+            assert_eq!(escrow.confirm(), Err(Error::NotAccepted));
+        }
+
+        #[ink::test]
+        fn when_nft_is_not_deposited_then_buyer_reclaims_only_payment() {
+            let (mut escrow, buyer, _) = create_contract_with_nft(10);
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        /// Returns account of the ECDSA key derived from `seed`
+        fn ecdsa_account(seed: u8) -> AccountId {
+            let secp = secp256k1::Secp256k1::new();
+            let secret_key = secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap();
+            let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize();
+            let mut account = [0u8; 32];
+            ink_env::hash_bytes::<Blake2x256>(&public_key, &mut account);
+            AccountId::from(account)
+        }
+
+        /// Signs `action` with the ECDSA key derived from `seed`
+        fn sign(seed: u8, escrow: &Escrow, action: SignedAction) -> [u8; 65] {
+            let secp = secp256k1::Secp256k1::new();
+            let secret_key = secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap();
+            let mut message_hash = [0u8; 32];
+            ink_env::hash_encoded::<Blake2x256, _>(
+                &(contract_id(), action, escrow.get_nonce()),
+                &mut message_hash,
+            );
+            let message = secp256k1::Message::from_slice(&message_hash).unwrap();
+            let (recovery_id, compact) = secp
+                .sign_ecdsa_recoverable(&message, &secret_key)
+                .serialize_compact();
+            let mut signature = [0u8; 65];
+            signature[..64].copy_from_slice(&compact);
+            signature[64] = recovery_id.to_i32() as u8;
+            signature
+        }
+
+        fn create_contract_with_signer(deposit: Balance, seed: u8) -> Escrow {
+            create_funded_contract(
+                deposit,
+                Terms {
+                    buyer: Some(ecdsa_account(seed)),
+                    ..Default::default()
+                },
+            )
+            .0
+        }
+
+        #[ink::test]
+        fn when_buyer_signs_confirm_then_relayer_confirms() {
+            let mut escrow = create_contract_with_signer(10, 1);
+            let signature = sign(1, &escrow, SignedAction::Confirm);
+            assert_eq!(escrow.get_buyer(), ecdsa_account(1));
+
+            set_caller(get_default_test_accounts().django);
+            escrow
+                .confirm_with_signature(signature)
+                .expect("Confirm with signature failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(escrow.get_nonce(), 1);
+            assert_eq!(get_balance(escrow.get_seller()), 10);
+        }
+
+        #[ink::test]
+        fn when_refund_is_signed_not_by_seller_then_error() {
+            let mut escrow = create_contract_with_signer(10, 1);
+            let signature = sign(1, &escrow, SignedAction::Refund);
+
+            set_caller(get_default_test_accounts().django);
+            assert_eq!(
+                escrow.refund_with_signature(signature),
+                Err(Error::InvalidSignature)
+            );
+            assert_eq!(escrow.get_nonce(), 0);
+        }
+
+        #[ink::test]
+        fn when_signature_is_for_other_action_then_error() {
+            let mut escrow = create_contract_with_signer(10, 1);
+            let signature = sign(1, &escrow, SignedAction::Refund);
+
+            assert_eq!(
+                escrow.confirm_with_signature(signature),
+                Err(Error::InvalidSignature)
+            );
+            assert_eq!(escrow.get_state(), State::Funded);
+        }
+
+        fn contribute(escrow: &mut Escrow, contributor: AccountId, value: Balance) {
+            set_caller(contributor);
+            transfer_in(value);
+            escrow.contribute().expect("Contribute failed!");
+        }
+
+        fn create_contract_with_contributor(
+            deposit: Balance,
+            confirmation_threshold: u32,
+        ) -> (Escrow, AccountId, AccountId, AccountId) {
+            let contributor = get_default_test_accounts().django;
+            let terms = Terms {
+                contributors: vec![contributor],
+                confirmation_threshold,
+                ..Default::default()
+            };
+            let (escrow, buyer, seller) = create_funded_contract(deposit, terms);
+
+            (escrow, buyer, seller, contributor)
+        }
+
+        #[ink::test]
+        fn when_contributing_then_deposit_increases() {
+            let (mut escrow, buyer, _, django) = create_contract_with_contributor(10, 0);
+            contribute(&mut escrow, django, 5);
+            contribute(&mut escrow, django, 15);
+            assert_eq!(escrow.get_deposit(), 30);
+            assert_eq!(escrow.get_contributors(), vec![buyer, django]);
+            assert_eq!(escrow.get_contribution(django), 20);
+        }
+
+        #[ink::test]
+        fn when_contributing_nothing_then_error() {
+            let (mut escrow, _, _, django) = create_contract_with_contributor(10, 0);
+            set_caller(django);
+            assert_eq!(escrow.contribute(), Err(Error::ZeroContribution));
+        }
+
+        #[ink::test]
+        fn when_seller_contributes_then_they_cannot_confirm() {
+            let (mut escrow, _, seller, _) = create_contract_with_contributor(10, 0);
+            set_caller(seller);
+            transfer_in(1);
+            assert_eq!(escrow.contribute(), Err(Error::ContributeNotAllowed));
+            assert_eq!(escrow.confirm(), Err(Error::ConfirmNotAsBuyer));
+            assert_eq!(escrow.get_state(), State::Funded);
+            assert_eq!(escrow.get_deposit(), 10);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Payees and the fee collector cannot contribute")]
+        fn when_payee_is_allowed_to_contribute_then_constructor_panics() {
+            let terms = Terms {
+                contributors: vec![get_default_test_accounts().frank],
+                ..Default::default()
+            };
+            create_funded_contract(10, terms);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Too many contributors")]
+        fn when_too_many_contributors_are_allowed_then_constructor_panics() {
+            let terms = Terms {
+                contributors: (0..=MAX_CONTRIBUTORS as u8)
+                    .map(|i| AccountId::from([i; 32]))
+                    .collect(),
+                ..Default::default()
+            };
+            create_funded_contract(10, terms);
+        }
+
+        #[ink::test]
+        #[should_panic(
+            expected = "Confirmation threshold cannot exceed the number of contributors"
+        )]
+        fn when_confirmation_threshold_exceeds_contributors_then_constructor_panics() {
+            create_contract_with_contributor(10, 3);
+        }
+
+        #[ink::test]
+        fn when_deal_is_not_accepted_then_confirmation_is_not_recorded() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            set_caller(buyer);
+            assert_eq!(escrow.confirm(), Err(Error::NotAccepted));
+            assert!(escrow.get_confirmations().is_empty());
+
+            accept(&mut escrow, seller, 5);
+            confirm(&mut escrow, buyer);
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 15);
+        }
+
+        #[ink::test]
+        fn when_threshold_is_not_reached_then_deposit_is_held() {
+            let (mut escrow, buyer, seller, django) = create_contract_with_contributor(10, 2);
+            contribute(&mut escrow, django, 10);
+
+            set_caller(buyer);
+            escrow.confirm().expect("Confirm failed!");
+            assert_eq!(escrow.confirm(), Err(Error::AlreadyConfirmed));
+            assert_eq!(escrow.get_state(), State::Funded);
+
+            set_caller(django);
+            escrow.confirm().expect("Confirm failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 20);
+        }
+
+        #[ink::test]
+        fn when_refunding_then_contributors_are_paid_pro_rata() {
+            let (mut escrow, buyer, seller, django) = create_contract_with_contributor(10, 0);
+            contribute(&mut escrow, django, 30);
+
+            refund(&mut escrow, seller);
+            assert_eq!(get_balance(buyer), 10);
+            assert_eq!(get_balance(django), 30);
+        }
+
+        fn create_contract_with_payees(
+            deposit: Balance,
+        ) -> (Escrow, AccountId, AccountId, AccountId) {
+            let accounts = get_default_test_accounts();
+            let (buyer, seller, supplier) = (accounts.eve, accounts.frank, accounts.django);
+
+            set_caller(buyer);
+            set_balance(contract_id(), deposit);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(deposit);
+            let escrow = Escrow::new(vec![(seller, 7_000), (supplier, 3_000)], Terms::default());
+
+            (escrow, buyer, seller, supplier)
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Payee shares must sum up to the whole payout")]
+        fn when_payee_shares_do_not_sum_up_then_instantiation_fails() {
+            let accounts = get_default_test_accounts();
+            Escrow::new(
+                vec![(accounts.frank, 7_000), (accounts.django, 2_000)],
+                Terms::default(),
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Payee shares must be positive")]
+        fn when_payee_share_is_zero_then_instantiation_fails() {
+            let accounts = get_default_test_accounts();
+            Escrow::new(
+                vec![(accounts.frank, 0), (accounts.django, MAX_BASIS_POINTS)],
+                Terms::default(),
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Payees must be unique")]
+        fn when_payee_is_listed_twice_then_instantiation_fails() {
+            let accounts = get_default_test_accounts();
+            Escrow::new(
+                vec![(accounts.frank, 5_000), (accounts.frank, 5_000)],
+                Terms::default(),
+            );
+        }
+
+        #[ink::test]
+        fn when_confirming_then_payout_is_split_among_payees() {
+            let (mut escrow, buyer, seller, supplier) = create_contract_with_payees(11);
+            confirm(&mut escrow, buyer);
+            assert_eq!(get_balance(supplier), 3);
+            assert_eq!(get_balance(seller), 8);
+        }
+
+        #[ink::test]
+        fn when_payee_waives_then_their_share_is_refunded() {
+            let (mut escrow, buyer, seller, supplier) = create_contract_with_payees(10);
+            set_caller(supplier);
+            escrow.waive().expect("Waive failed!");
+            assert_eq!(escrow.waive(), Err(Error::AlreadyWaived));
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(get_balance(buyer), 3);
+            assert_eq!(get_balance(seller), 7);
+            assert_eq!(get_balance(supplier), 0);
+        }
+
+        #[ink::test]
+        fn when_all_payees_waive_then_deal_is_refunded() {
+            let (mut escrow, buyer, seller, supplier) = create_contract_with_payees(10);
+            set_caller(seller);
+            escrow.waive().expect("Waive failed!");
+            set_caller(supplier);
+            escrow.waive().expect("Waive failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        fn when_waiving_not_as_payee_then_error() {
+            let (mut escrow, buyer, _, _) = create_contract_with_payees(10);
+            set_caller(buyer);
+            assert_eq!(escrow.waive(), Err(Error::WaiveNotAsPayee));
+        }
+
+        fn create_contract_with_subscription(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let terms = Terms {
+                subscription: Some((10, 3)),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        #[ink::test]
+        fn when_period_has_not_elapsed_then_claim_fails() {
+            let (mut escrow, _, seller) = create_contract_with_subscription(30);
+            advance_blocks(9);
+            set_caller(seller);
+            assert_eq!(escrow.claim_period(), Err(Error::PeriodNotElapsed));
+        }
+
+        #[ink::test]
+        fn when_periods_elapse_then_seller_claims_them_one_by_one() {
+            let (mut escrow, _, seller) = create_contract_with_subscription(31);
+            advance_blocks(20);
+            set_caller(seller);
+            escrow.claim_period().expect("Claim failed!");
+            escrow.claim_period().expect("Claim failed!");
+            assert_eq!(escrow.claim_period(), Err(Error::PeriodNotElapsed));
+            assert_eq!(get_balance(seller), 20);
+            assert_eq!(escrow.get_subscription().unwrap().claimed, 2);
+
+            advance_blocks(10);
+            escrow.claim_period().expect("Claim failed!");
+            assert_eq!(get_balance(seller), 31);
+            assert_eq!(escrow.get_state(), State::Released);
+        }
+
+        #[ink::test]
+        fn when_buyer_cancels_then_elapsed_periods_are_paid_and_rest_refunded() {
+            let (mut escrow, buyer, seller) = create_contract_with_subscription(30);
+            advance_blocks(10);
+            set_caller(buyer);
+            escrow.cancel().expect("Cancel failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
+            assert_eq!(get_balance(buyer), 20);
+        }
+
+        #[ink::test]
+        fn when_buyer_cancels_before_first_period_then_deal_is_refunded() {
+            let (mut escrow, buyer, seller) = create_contract_with_subscription(30);
+            set_caller(buyer);
+            escrow.cancel().expect("Cancel failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(seller), 0);
+            assert_eq!(get_balance(buyer), 30);
+        }
+
+        #[ink::test]
+        fn when_deal_is_accepted_late_then_subscription_starts_on_acceptance() {
+            let terms = Terms {
+                subscription: Some((10, 3)),
+                collateral: 5,
+                ..Default::default()
+            };
+            let (mut escrow, _, seller) = create_funded_contract(30, terms);
+            advance_blocks(20);
+            set_caller(seller);
+            assert_eq!(escrow.claim_period(), Err(Error::PeriodNotElapsed));
+
+            accept(&mut escrow, seller, 5);
+            assert_eq!(
+                escrow.get_subscription().unwrap().start,
+                ink_env::block_number::<ink_env::DefaultEnvironment>()
+            );
+            advance_blocks(9);
+            assert_eq!(escrow.claim_period(), Err(Error::PeriodNotElapsed));
+            advance_blocks(1);
+            escrow.claim_period().expect("Claim failed!");
+            assert_eq!(get_balance(seller), 10);
+        }
+
+        #[ink::test]
+        fn when_cancelling_without_subscription_then_error() {
+            let (mut escrow, buyer, _) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            assert_eq!(escrow.cancel(), Err(Error::NoSchedule));
+        }
+
+        fn create_contract_with_vesting(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let terms = Terms {
+                vesting: Some((1_000, 2_000)),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
+
+        fn set_timestamp(timestamp: Timestamp) {
+            ink_env::test::set_block_timestamp::<ink_env::DefaultEnvironment>(timestamp);
+        }
+
+        #[ink::test]
+        fn when_vesting_has_not_started_then_nothing_is_releasable() {
+            let (mut escrow, _, seller) = create_contract_with_vesting(100);
+            set_timestamp(500);
+            assert_eq!(escrow.releasable(), Ok(0));
+            set_caller(seller);
+            assert_eq!(escrow.withdraw_vested(), Err(Error::NothingVested));
+        }
+
+        #[ink::test]
+        fn when_time_passes_then_seller_withdraws_vested_part() {
+            let (mut escrow, _, seller) = create_contract_with_vesting(100);
+            set_caller(seller);
+            set_timestamp(1_250);
+            assert_eq!(escrow.releasable(), Ok(25));
+            escrow.withdraw_vested().expect("Withdraw failed!");
+            assert_eq!(get_balance(seller), 25);
+            assert_eq!(escrow.releasable(), Ok(0));
+
+            set_timestamp(3_000);
+            assert_eq!(escrow.releasable(), Ok(75));
+            escrow.withdraw_vested().expect("Withdraw failed!");
+            assert_eq!(get_balance(seller), 100);
+            assert_eq!(escrow.get_state(), State::Released);
+        }
+
+        #[ink::test]
+        fn when_buyer_cancels_vesting_then_unvested_part_is_refunded() {
+            let (mut escrow, buyer, seller) = create_contract_with_vesting(100);
+            set_timestamp(1_400);
+            set_caller(buyer);
+            escrow.cancel().expect("Cancel failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 40);
+            assert_eq!(get_balance(buyer), 60);
+        }
+
+        #[ink::test]
+        fn when_vesting_is_cancelled_after_withdrawal_then_nothing_is_releasable() {
+            let (mut escrow, buyer, seller) = create_contract_with_vesting(100);
+            set_timestamp(1_250);
+            set_caller(seller);
+            escrow.withdraw_vested().expect("Withdraw failed!");
+            set_caller(buyer);
+            escrow.cancel().expect("Cancel failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 25);
+            assert_eq!(get_balance(buyer), 75);
+
+            set_timestamp(1_500);
+            assert_eq!(escrow.releasable(), Ok(0));
+        }
+
+        #[ink::test]
+        fn when_deal_is_confirmed_then_lifecycle_events_are_emitted() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            confirm(&mut escrow, buyer);
+
+            let events = recorded_events();
+            assert!(events.iter().any(|event| matches!(
+                event,
+                Event::Funded(Funded { escrow, buyer: b, seller: s, deposit: 10, .. })
+                    if *escrow == contract_id() && *b == buyer && *s == seller
+            )));
+            assert!(events.iter().any(|event| matches!(
+                event,
+                Event::Confirmed(Confirmed { escrow, buyer: b, seller: s })
+                    if *escrow == contract_id() && *b == buyer && *s == seller
+            )));
+        }
+
+        #[ink::test]
+        fn when_deal_awaits_acceptance_then_funded_is_emitted_on_accept() {
+            let (mut escrow, _, seller) = create_contract_with_collateral(10, 5);
+            assert!(!recorded_events()
+                .iter()
+                .any(|event| matches!(event, Event::Funded(_))));
+
+            accept(&mut escrow, seller, 5);
+            assert!(matches!(
+                recorded_events().last(),
+                Some(Event::Funded(Funded { deposit: 10, .. }))
+            ));
+        }
 
-#![cfg_attr(not(feature = "std"), no_std)]
+        #[ink::test]
+        fn when_dispute_is_resolved_then_lifecycle_events_are_emitted() {
+            let (mut escrow, buyer, _, arbiter) = create_contract_with_arbiter(10);
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raise dispute failed!");
+            set_caller(arbiter);
+            escrow.resolve(10, 0).expect("Resolve failed!");
 
-use ink_lang as ink;
+            let events = recorded_events();
+            assert!(events.iter().any(
+                |event| matches!(event, Event::Disputed(Disputed { by, .. }) if *by == buyer)
+            ));
+            assert!(events.iter().any(|event| matches!(
+                event,
+                Event::Resolved(Resolved {
+                    buyer_share: 10,
+                    seller_share: 0,
+                    ..
+                })
+            )));
+            assert!(events
+                .iter()
+                .any(|event| matches!(event, Event::Refunded(_))));
+        }
 
-#[ink::contract]
-pub mod escrow {
-    use scale::{Decode, Encode};
+        fn create_contract_with_confirmation_window(
+            deposit: Balance,
+        ) -> (Escrow, AccountId, AccountId) {
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                delivery_deadline: Some(now + 2),
+                order_ref: Some(Hash::from([1; 32])),
+                confirmation_window: Some(3),
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
+        }
 
-    #[ink(storage)]
-    pub struct Escrow {
-        /// Buyer's account
-        buyer: AccountId,
+        #[ink::test]
+        fn when_goods_are_shipped_then_confirmation_window_starts() {
+            let (mut escrow, _, seller) = create_contract_with_confirmation_window(10);
+            assert_eq!(escrow.get_order_ref(), Some(Hash::from([1; 32])));
+            advance_blocks(1);
+            set_caller(seller);
+            escrow
+                .mark_shipped(Hash::from([2; 32]))
+                .expect("Mark shipped failed!");
+            assert_eq!(escrow.get_shipment().unwrap().proof, Hash::from([2; 32]));
+            assert_eq!(escrow.get_confirmation_deadline(), Some(4));
+            assert!(matches!(
+                recorded_events().last(),
+                Some(Event::Shipped(Shipped {
+                    confirmation_deadline: Some(4),
+                    ..
+                }))
+            ));
 
-        /// Sellers's account
-        seller: AccountId,
+            advance_blocks(3);
+            assert_eq!(escrow.collect(), Err(Error::ConfirmationDeadlineNotPassed));
+            advance_blocks(1);
+            escrow.collect().expect("Collect failed!");
+            assert_eq!(get_balance(seller), 10);
+        }
 
-        /// Deposit value
-        deposit: Balance,
-    }
+        #[ink::test]
+        fn when_goods_are_shipped_then_buyer_cannot_reclaim() {
+            let (mut escrow, buyer, seller) = create_contract_with_confirmation_window(10);
+            set_caller(seller);
+            escrow
+                .mark_shipped(Hash::from([2; 32]))
+                .expect("Mark shipped failed!");
+            assert_eq!(
+                escrow.mark_shipped(Hash::from([3; 32])),
+                Err(Error::AlreadyShipped)
+            );
 
-    /// Error scenarios in escrow contract
-    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
-    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
-    pub enum Error {
-        /// Someone else than buyer tries to confirm
-        ConfirmNotAsBuyer,
+            advance_blocks(3);
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::AlreadyShipped));
+        }
 
-        /// Someone else than seller tries to refund
-        RefundNotAsSeller,
+        #[ink::test]
+        fn when_delivery_deadline_has_passed_then_goods_cannot_be_marked_shipped() {
+            let (mut escrow, buyer, seller) = create_contract_with_confirmation_window(10);
+            advance_blocks(3);
+            set_caller(seller);
+            assert_eq!(
+                escrow.mark_shipped(Hash::from([2; 32])),
+                Err(Error::DeliveryDeadlinePassed)
+            );
 
-        /// requested transfer failed, this can be the case if the contract does not
-        /// have sufficient free funds or if the transfer would have brought the
-        /// contract's balance below minimum balance
-        TransferFailed,
-    }
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), 10);
+        }
 
-    /// An event emitted when token transfer occurs
-    #[ink(event)]
-    pub struct Transfer {
-        #[ink(topic)]
-        to: AccountId,
-        value: Balance,
-    }
+        #[ink::test]
+        #[should_panic(expected = "Confirmation window cannot be combined with hashlock")]
+        fn when_confirmation_window_is_combined_with_hashlock_then_constructor_panics() {
+            let terms = Terms {
+                delivery_deadline: Some(2),
+                hashlock: Some(Hashlock::Sha2x256([0; 32])),
+                confirmation_window: Some(3),
+                ..Default::default()
+            };
+            create_funded_contract(10, terms);
+        }
 
-    pub type Result<T> = core::result::Result<T, Error>;
+        #[ink::test]
+        fn when_deposit_is_hashlocked_then_goods_cannot_be_marked_shipped() {
+            let (mut escrow, buyer, seller) = create_contract_with_hashlock(10, b"secret");
+            set_caller(seller);
+            assert_eq!(
+                escrow.mark_shipped(Hash::from([2; 32])),
+                Err(Error::Hashlocked)
+            );
+            assert_eq!(escrow.get_shipment(), None);
 
-    impl Escrow {
-        /// Instantiates new escrow contract with buyer as contract author
-        #[ink(constructor, payable)]
-        pub fn new(seller: AccountId) -> Self {
-            let escrow = Self {
-                buyer: Self::env().caller(),
-                seller,
-                deposit: Self::env().transferred_value(),
+            advance_blocks(3);
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        fn when_seller_cannot_be_paid_without_buyer_then_shipping_does_not_block_reclaim() {
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                delivery_deadline: Some(now + 2),
+                ..Default::default()
             };
-            Self::env().emit_event(Transfer {
-                to: Self::env().account_id(),
-                value: escrow.deposit,
-            });
+            let (mut escrow, buyer, seller) = create_funded_contract(10, terms);
+            set_caller(seller);
+            escrow
+                .mark_shipped(Hash::from([2; 32]))
+                .expect("Mark shipped failed!");
+
+            advance_blocks(3);
+            set_caller(buyer);
+            escrow.reclaim().expect("Reclaim failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(buyer), 10);
+        }
 
+        #[ink::test]
+        fn when_shipped_deal_has_arbiter_then_buyer_cannot_reclaim() {
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                arbiter: Some(get_default_test_accounts().django),
+                delivery_deadline: Some(now + 2),
+                ..Default::default()
+            };
+            let (mut escrow, buyer, seller) = create_funded_contract(10, terms);
+            set_caller(seller);
             escrow
+                .mark_shipped(Hash::from([2; 32]))
+                .expect("Mark shipped failed!");
+
+            advance_blocks(3);
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::AlreadyShipped));
         }
 
-        /// Returns currently stored deposit
-        #[ink(message)]
-        pub fn get_deposit(&self) -> Balance {
-            self.deposit
+        #[ink::test]
+        fn when_not_seller_marks_shipped_then_error() {
+            let (mut escrow, buyer, _) = create_contract_with_confirmation_window(10);
+            set_caller(buyer);
+            assert_eq!(
+                escrow.mark_shipped(Hash::from([2; 32])),
+                Err(Error::ShipNotAsSeller)
+            );
         }
 
-        /// Returns buyers account
-        #[ink(message)]
-        pub fn get_buyer(&self) -> AccountId {
-            self.buyer
+        #[ink::test]
+        fn when_seller_accepts_partial_confirmation_then_rest_is_refunded() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            escrow.confirm_partial(6).expect("Confirm partial failed!");
+            assert_eq!(escrow.get_state(), State::Funded);
+
+            advance_blocks(100);
+            set_caller(seller);
+            escrow
+                .accept_settlement()
+                .expect("Accept settlement failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 6);
+            assert_eq!(get_balance(buyer), 4);
         }
 
-        /// Returns buyers account
-        #[ink(message)]
-        pub fn get_seller(&self) -> AccountId {
-            self.seller
+        #[ink::test]
+        fn when_seller_rejects_partial_confirmation_then_dispute_can_be_raised() {
+            let (mut escrow, buyer, seller, _) = create_contract_with_arbiter(10);
+            set_caller(buyer);
+            escrow.confirm_partial(6).expect("Confirm partial failed!");
+
+            set_caller(seller);
+            escrow.raise_dispute().expect("Raise dispute failed!");
+            assert_eq!(escrow.get_state(), State::Disputed);
         }
 
-        /// The buyer confirms delivery. Contract transfers a deposit to the seller and terminates itself
-        #[ink(message)]
-        pub fn confirm(&mut self) -> Result<()> {
-            let caller = Self::env().caller();
-            if caller != self.buyer {
-                return Err(Error::ConfirmNotAsBuyer);
-            }
-            self.make_transfer(self.seller, self.deposit)?;
-            self.env().terminate_contract(caller)
+        #[ink::test]
+        fn when_partial_confirmation_exceeds_deposit_then_error() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            set_caller(buyer);
+            assert_eq!(escrow.confirm_partial(11), Err(Error::InvalidShares));
+            set_caller(seller);
+            assert_eq!(escrow.confirm_partial(5), Err(Error::ConfirmNotAsBuyer));
         }
 
-        /// The seller aborts transaction. Contract refunds a deposit to the buyer and terminates itself
-        #[ink(message)]
-        pub fn refund(&mut self) -> Result<()> {
-            let caller = Self::env().caller();
-            if caller != self.seller {
-                return Err(Error::RefundNotAsSeller);
-            }
-            self.make_transfer(self.buyer, self.deposit)?;
-            self.env().terminate_contract(caller)
+        fn create_contract_with_approvers(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+            let accounts = get_default_test_accounts();
+            let terms = Terms {
+                approvers: vec![accounts.django, accounts.bob, accounts.charlie],
+                approval_threshold: 2,
+                ..Default::default()
+            };
+            create_funded_contract(deposit, terms)
         }
 
-        fn make_transfer(&mut self, to: AccountId, value: Balance) -> Result<()> {
-            self.env()
-                .transfer(to, value)
-                .map_err(|_| Error::TransferFailed)?;
-            self.env().emit_event(Transfer { to, value });
-            Ok(())
+        #[ink::test]
+        #[should_panic(
+            expected = "Approval threshold must be between one and the number of approvers"
+        )]
+        fn when_approval_threshold_exceeds_approvers_then_constructor_panics() {
+            let terms = Terms {
+                approvers: vec![get_default_test_accounts().django],
+                approval_threshold: 2,
+                ..Default::default()
+            };
+            create_funded_contract(10, terms);
         }
-    }
 
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink_env::AccountId;
+        #[ink::test]
+        fn when_threshold_of_approvals_is_reached_then_seller_is_paid() {
+            let accounts = get_default_test_accounts();
+            let (mut escrow, _, seller) = create_contract_with_approvers(10);
+            set_caller(accounts.django);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.approve_release(), Err(Error::AlreadyApproved));
+            assert_eq!(escrow.get_state(), State::Funded);
 
-        /// Imports `ink_lang` so we can use `#[ink::test]`.
-        use ink_lang as ink;
+            set_caller(accounts.charlie);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
+        }
 
-        fn get_default_test_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment>
-        {
-            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+        #[ink::test]
+        fn when_buyer_confirms_then_deposit_awaits_approvals() {
+            let accounts = get_default_test_accounts();
+            let (mut escrow, buyer, seller) = create_contract_with_approvers(10);
+            confirm(&mut escrow, buyer);
+            assert_eq!(escrow.get_confirmations(), vec![buyer]);
+            assert_eq!(escrow.get_state(), State::Funded);
+            assert_eq!(get_balance(seller), 0);
+
+            set_caller(accounts.django);
+            escrow.approve_release().expect("Approve failed!");
+            set_caller(accounts.bob);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
         }
 
-        fn set_balance(account_id: AccountId, balance: Balance) {
-            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
+        #[ink::test]
+        fn when_approval_is_revoked_then_it_does_not_count() {
+            let accounts = get_default_test_accounts();
+            let (mut escrow, _, _) = create_contract_with_approvers(10);
+            set_caller(accounts.django);
+            escrow.approve_release().expect("Approve failed!");
+            escrow.revoke_approval().expect("Revoke failed!");
+            assert_eq!(escrow.revoke_approval(), Err(Error::NotApproved));
+            assert!(escrow.get_approvals().is_empty());
+
+            set_caller(accounts.bob);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.get_state(), State::Funded);
         }
 
-        fn get_balance(account_id: AccountId) -> Balance {
-            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account_id)
-                .expect("Cannot get account balance")
+        #[ink::test]
+        fn when_approvals_are_pending_then_buyer_cannot_confirm_milestone() {
+            let accounts = get_default_test_accounts();
+            let terms = Terms {
+                milestones: vec![(3, Hash::from([0; 32])), (7, Hash::from([1; 32]))],
+                approvers: vec![accounts.django],
+                approval_threshold: 1,
+                ..Default::default()
+            };
+            let (mut escrow, buyer, seller) = create_funded_contract(10, terms);
+            set_caller(buyer);
+            assert_eq!(escrow.confirm_milestone(0), Err(Error::ApprovalsPending));
+            assert!(!escrow.get_milestones()[0].released);
+            assert_eq!(escrow.get_deposit(), 10);
+            assert_eq!(get_balance(seller), 0);
+
+            set_caller(accounts.django);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
         }
 
-        fn contract_id() -> AccountId {
-            ink_env::test::callee::<ink_env::DefaultEnvironment>()
+        #[ink::test]
+        fn when_approvals_are_pending_then_buyer_cannot_confirm_partially() {
+            let (mut escrow, buyer, seller) = create_contract_with_approvers(10);
+            set_caller(buyer);
+            assert_eq!(escrow.confirm_partial(6), Err(Error::ApprovalsPending));
+            assert_eq!(escrow.get_proposal(), None);
+            escrow.confirm_partial(0).expect("Confirm partial failed!");
+            assert_eq!(get_balance(seller), 0);
         }
 
-        fn set_caller(caller: AccountId) {
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+        #[ink::test]
+        fn when_approvals_are_pending_then_only_refunding_settlement_is_accepted() {
+            let accounts = get_default_test_accounts();
+            let (mut escrow, buyer, seller) = create_contract_with_approvers(10);
+            set_caller(seller);
+            escrow
+                .propose_settlement(4, 6, 10)
+                .expect("Propose failed!");
+            set_caller(buyer);
+            assert_eq!(escrow.accept_settlement(), Err(Error::ApprovalsPending));
+            assert_eq!(escrow.get_state(), State::Funded);
+            assert_eq!(escrow.get_deposit(), 10);
+            assert!(!recorded_events()
+                .iter()
+                .any(|event| matches!(event, Event::SettlementAccepted(_))));
+
+            set_caller(accounts.django);
+            escrow.approve_release().expect("Approve failed!");
+            set_caller(accounts.bob);
+            escrow.approve_release().expect("Approve failed!");
+            assert_eq!(escrow.get_state(), State::Released);
+            assert_eq!(get_balance(seller), 10);
         }
 
-        fn create_contract(deposit: Balance) -> (Escrow, AccountId, AccountId) {
+        #[ink::test]
+        fn when_approvals_are_pending_then_buyer_can_still_settle_on_refund() {
+            let (mut escrow, buyer, seller) = create_contract_with_approvers(10);
+            set_caller(seller);
+            escrow
+                .propose_settlement(10, 0, 10)
+                .expect("Propose failed!");
+            set_caller(buyer);
+            escrow.accept_settlement().expect("Accept failed!");
+            assert_eq!(escrow.get_state(), State::Refunded);
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        fn when_not_approver_approves_then_error() {
+            let (mut escrow, buyer, _) = create_contract_with_approvers(10);
+            set_caller(buyer);
+            assert_eq!(escrow.approve_release(), Err(Error::ApproveNotAsApprover));
+        }
+
+        fn create_contract_with_guardian(
+            deposit: Balance,
+        ) -> (Escrow, AccountId, AccountId, AccountId) {
+            let guardian = get_default_test_accounts().django;
+            let terms = Terms {
+                guardian: Some(guardian),
+                ..Default::default()
+            };
+            let (escrow, buyer, seller) = create_funded_contract(deposit, terms);
+
+            (escrow, buyer, seller, guardian)
+        }
+
+        #[ink::test]
+        fn when_paused_then_deal_cannot_be_settled() {
+            let (mut escrow, buyer, seller, guardian) = create_contract_with_guardian(10);
+            set_caller(guardian);
+            escrow.pause().expect("Pause failed!");
+            assert!(escrow.is_paused());
+
+            set_caller(buyer);
+            assert_eq!(escrow.confirm(), Err(Error::Paused));
+            set_caller(seller);
+            assert_eq!(escrow.refund(), Err(Error::Paused));
+            assert_eq!(escrow.get_deposit(), 10);
+        }
+
+        #[ink::test]
+        fn when_unpaused_then_deal_can_be_settled() {
+            let (mut escrow, buyer, seller, guardian) = create_contract_with_guardian(10);
+            set_caller(guardian);
+            escrow.pause().expect("Pause failed!");
+            escrow.unpause().expect("Unpause failed!");
+            assert_eq!(escrow.unpause(), Err(Error::NotPaused));
+
+            confirm(&mut escrow, buyer);
+            assert_eq!(get_balance(seller), 10);
+        }
+
+        #[ink::test]
+        fn when_paused_then_payouts_are_blocked() {
             let accounts = get_default_test_accounts();
-            let buyer = accounts.eve;
-            let seller = accounts.frank;
+            let (arbiter, approver, guardian) = (accounts.django, accounts.charlie, accounts.bob);
+            let mut hash = [0u8; 32];
+            ink_env::hash_bytes::<Sha2x256>(b"secret", &mut hash);
+            let now = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let terms = Terms {
+                arbiter: Some(arbiter),
+                delivery_deadline: Some(now + 2),
+                hashlock: Some(Hashlock::Sha2x256(hash)),
+                approvers: vec![approver],
+                approval_threshold: 1,
+                guardian: Some(guardian),
+                ..Default::default()
+            };
+            let (mut escrow, buyer, seller) = create_funded_contract(10, terms);
+            set_caller(seller);
+            assert_eq!(escrow.propose_settlement(5, 5, 10), Ok(()));
+            set_caller(guardian);
+            escrow.pause().expect("Pause failed!");
 
             set_caller(buyer);
-            set_balance(contract_id(), deposit);
-            let escrow = Escrow::new(seller);
+            assert_eq!(escrow.confirm_with_signature([0; 65]), Err(Error::Paused));
+            assert_eq!(escrow.confirm_milestone(0), Err(Error::Paused));
+            assert_eq!(escrow.reclaim(), Err(Error::Paused));
+            assert_eq!(escrow.cancel(), Err(Error::Paused));
+            assert_eq!(escrow.accept_settlement(), Err(Error::Paused));
+            set_caller(seller);
+            assert_eq!(escrow.refund_with_signature([0; 65]), Err(Error::Paused));
+            assert_eq!(escrow.waive(), Err(Error::Paused));
+            assert_eq!(escrow.collect(), Err(Error::Paused));
+            assert_eq!(escrow.claim_period(), Err(Error::Paused));
+            assert_eq!(escrow.withdraw_vested(), Err(Error::Paused));
+            assert_eq!(escrow.claim(b"secret".to_vec()), Err(Error::Paused));
+            set_caller(approver);
+            assert_eq!(escrow.approve_release(), Err(Error::Paused));
 
-            (escrow, buyer, seller)
+            assert_eq!(escrow.get_state(), State::Funded);
+            assert_eq!(escrow.get_deposit(), 10);
+            assert_eq!(get_balance(contract_id()), 10);
+            assert_eq!(escrow.get_nonce(), 0);
+            assert!(escrow.get_approvals().is_empty());
+            assert!(!escrow.get_payees()[0].waived);
+            assert!(!recorded_events()
+                .iter()
+                .any(|event| matches!(event, Event::SecretRevealed(_))));
+
+            set_caller(buyer);
+            escrow.raise_dispute().expect("Raise dispute failed!");
+            set_caller(arbiter);
+            assert_eq!(escrow.resolve(5, 5), Err(Error::Paused));
+            assert_eq!(escrow.get_state(), State::Disputed);
+            assert_eq!(escrow.get_deposit(), 10);
         }
 
-        fn confirm(escrow: &mut Escrow, buyer: AccountId) -> () {
+        #[ink::test]
+        fn when_not_guardian_pauses_then_error() {
+            let (mut escrow, buyer, _, _) = create_contract_with_guardian(10);
             set_caller(buyer);
-            escrow.confirm().expect("Confirm failed!")
+            assert_eq!(escrow.pause(), Err(Error::PauseNotAsGuardian));
+            assert!(!escrow.is_paused());
         }
 
-        fn refund(escrow: &mut Escrow, seller: AccountId) -> () {
+        #[ink::test]
+        fn when_seller_is_paid_then_reentering_finds_deal_released() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            confirm(&mut escrow, buyer);
+
             set_caller(seller);
-            escrow.refund().expect("Refund failed!")
+            assert_eq!(escrow.refund(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.waive(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.accept_settlement(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.get_deposit(), 0);
+            assert_eq!(get_balance(seller), 10);
         }
 
         #[ink::test]
-        fn when_constructor_is_called_then_contract_has_deposit() {
-            const DEPOSIT: Balance = 123;
-            let (escrow, buyer, seller) = create_contract(DEPOSIT);
-            assert_eq!(escrow.buyer, buyer);
-            assert_eq!(escrow.seller, seller);
-            assert_eq!(get_balance(contract_id()), DEPOSIT);
+        fn when_buyer_is_refunded_then_reentering_finds_deal_refunded() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            refund(&mut escrow, seller);
+
+            set_caller(buyer);
+            assert_eq!(escrow.reclaim(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.confirm(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.cancel(), Err(Error::AlreadySettled));
+            assert_eq!(escrow.get_deposit(), 0);
+            assert_eq!(get_balance(buyer), 10);
         }
 
         #[ink::test]
-        fn when_buyer_confirms_then_seller_receives_deposit() {
-            const DEPOSIT: Balance = 10;
-            let (mut escrow, buyer, _) = create_contract(DEPOSIT);
+        fn when_collateral_is_returned_then_reentering_finds_deal_released() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            confirm(&mut escrow, buyer);
 
-            let should_terminate = move || confirm(&mut escrow, buyer);
-            // unfortunately we can't test much in this UT, since below test only outcome of
-            // self.env().terminate_contract(); the confirm() panics in off-chain testing environment
-            // so there's no easy way to test state directly of the escrow contract after confirm()
-            ink_env::test::assert_contract_termination::<ink_env::DefaultEnvironment, _>(
-                should_terminate,
-                buyer,
-                DEPOSIT,
-            );
-            // we can only indirectly check account balances
+            set_caller(seller);
+            assert_eq!(escrow.refund(), Err(Error::AlreadySettled));
+            assert_eq!(get_balance(seller), 15);
             assert_eq!(get_balance(contract_id()), 0);
-            // below incorrectly returns account values (should be other way round)
-            // assert_eq!(get_balance(seller), 0);
-            // assert_eq!(get_balance(buyer), DEPOSIT);
         }
 
         #[ink::test]
-        fn when_not_buyer_confirms_then_error_is_returned() {
-            let (mut escrow, _, seller) = create_contract(0);
+        fn when_last_period_is_claimed_then_reentering_finds_deal_released() {
+            let (mut escrow, buyer, seller) = create_contract_with_subscription(30);
+            advance_blocks(30);
             set_caller(seller);
-            assert_eq!(escrow.confirm(), Err(Error::ConfirmNotAsBuyer));
+            for _ in 0..3 {
+                escrow.claim_period().expect("Claim failed!");
+            }
+
+            assert_eq!(escrow.claim_period(), Err(Error::AlreadySettled));
+            set_caller(buyer);
+            assert_eq!(escrow.cancel(), Err(Error::AlreadySettled));
+            assert_eq!(get_balance(seller), 30);
         }
 
         #[ink::test]
-        fn when_seller_refunds_then_deposit_is_returned_to_buyer() {
-            const DEPOSIT: Balance = 1123;
-            let (mut escrow, _, seller) = create_contract(DEPOSIT);
+        fn when_milestone_is_paid_then_it_is_already_deducted() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[3, 7]);
+            set_caller(buyer);
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
 
-            let should_terminate = move || refund(&mut escrow, seller);
-            // unfortunately we can't test much in this UT, since below test only outcome of
-            // self.env().terminate_contract(); the refund() panics in off-chain testing environment
-            // so there's no easy way to test state directly of the escrow contract after refund()
-            ink_env::test::assert_contract_termination::<ink_env::DefaultEnvironment, _>(
-                should_terminate,
-                seller,
-                DEPOSIT,
+            assert_eq!(
+                escrow.confirm_milestone(0),
+                Err(Error::MilestoneAlreadyReleased)
             );
-            // we can only indirectly check account balances
-            assert_eq!(get_balance(contract_id()), 0);
-            // below incorrectly returns account values (should be other way round)
-            // assert_eq!(get_balance(seller), DEPOSIT);
-            // assert_eq!(get_balance(buyer), 0);
+            assert_eq!(escrow.get_deposit(), 7);
+            assert_eq!(get_balance(seller), 3);
         }
 
         #[ink::test]
-        fn when_not_seller_refunds_then_error_is_returned() {
-            let (mut escrow, buyer, _) = create_contract(0);
+        fn when_seller_receives_payment_then_deal_is_already_released() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            reenter_on_next_transfer();
+            confirm(&mut escrow, buyer);
+
+            assert_eq!(
+                observed_by(seller),
+                vec![(State::Released, 0, Some(Err(Error::AlreadySettled)))]
+            );
+            assert_eq!(get_balance(seller), 10);
+        }
+
+        #[ink::test]
+        fn when_buyer_receives_refund_then_deal_is_already_refunded() {
+            let (mut escrow, buyer, seller) = create_funded_contract(10, Terms::default());
+            reenter_on_next_transfer();
+            refund(&mut escrow, seller);
+
+            assert_eq!(
+                observed_by(buyer),
+                vec![(State::Refunded, 0, Some(Err(Error::AlreadySettled)))]
+            );
+            assert_eq!(get_balance(buyer), 10);
+        }
+
+        #[ink::test]
+        fn when_seller_receives_collateral_back_then_deal_is_already_released() {
+            let (mut escrow, buyer, seller) = create_contract_with_collateral(10, 5);
+            accept(&mut escrow, seller, 5);
+            reenter_on_next_transfer();
+            confirm(&mut escrow, buyer);
+
+            assert_eq!(
+                observed_by(seller),
+                vec![
+                    (State::Released, 0, Some(Err(Error::AlreadySettled))),
+                    (State::Released, 0, None)
+                ]
+            );
+            assert_eq!(get_balance(seller), 15);
+        }
+
+        #[ink::test]
+        fn when_seller_receives_milestone_then_it_is_already_deducted() {
+            let (mut escrow, buyer, seller) = create_contract_with_milestones(&[3, 7]);
             set_caller(buyer);
-            assert_eq!(escrow.refund(), Err(Error::RefundNotAsSeller));
+            escrow
+                .confirm_milestone(0)
+                .expect("Confirm milestone failed!");
+
+            assert_eq!(observed_by(seller), vec![(State::Funded, 7, None)]);
+            assert!(escrow.get_milestones()[0].released);
         }
     }
 }
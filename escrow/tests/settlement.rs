@@ -0,0 +1,219 @@
+//! Settlement scenarios checking exact balances of every party.
+//!
+//! Unit tests in `lib.rs` check behaviour of single messages. Here whole deals are played out
+//! message by message in the off-chain test environment of ink!. Nothing is deployed and no gas
+//! nor storage deposit is charged. Every party starts with a known balance, value transferred along
+//! with a call is debited from the caller by `call_as()` (the off-chain environment does not do
+//! that on its own), and after settlement the balance of every party, including the contract
+//! itself, is asserted exactly. Where it matters, i.e. when the buyer closes the deal, the storage
+//! deposit is simulated by hand.
+
+use escrow::escrow::{Escrow, Fee, Terms};
+use ink_env::{test, AccountId, DefaultEnvironment};
+use ink_lang as ink;
+
+type Balance = <DefaultEnvironment as ink_env::Environment>::Balance;
+
+const INITIAL_BALANCE: Balance = 1_000;
+const DEPOSIT: Balance = 100;
+
+struct Parties {
+    buyer: AccountId,
+    seller: AccountId,
+    arbiter: AccountId,
+    collector: AccountId,
+}
+
+fn parties() -> Parties {
+    let accounts = test::default_accounts::<DefaultEnvironment>();
+    let parties = Parties {
+        buyer: accounts.eve,
+        seller: accounts.frank,
+        arbiter: accounts.django,
+        collector: accounts.charlie,
+    };
+    for account in [
+        parties.buyer,
+        parties.seller,
+        parties.arbiter,
+        parties.collector,
+    ] {
+        set_balance(account, INITIAL_BALANCE);
+    }
+    set_balance(contract_id(), 0);
+    parties
+}
+
+fn contract_id() -> AccountId {
+    test::callee::<DefaultEnvironment>()
+}
+
+fn balance(account: AccountId) -> Balance {
+    test::get_account_balance::<DefaultEnvironment>(account).expect("Cannot get account balance")
+}
+
+fn set_balance(account: AccountId, balance: Balance) {
+    test::set_account_balance::<DefaultEnvironment>(account, balance);
+}
+
+/// Makes `caller` the caller of the next message, moving `value` from them to the contract
+fn call_as(caller: AccountId, value: Balance) {
+    test::set_caller::<DefaultEnvironment>(caller);
+    transfer_directly(caller, value);
+    test::set_value_transferred::<DefaultEnvironment>(value);
+}
+
+/// Moves `value` from `from` to the contract without calling it
+fn transfer_directly(from: AccountId, value: Balance) {
+    set_balance(from, balance(from) - value);
+    set_balance(contract_id(), balance(contract_id()) + value);
+}
+
+fn instantiate(parties: &Parties, terms: Terms) -> Escrow {
+    call_as(parties.buyer, DEPOSIT);
+    Escrow::new(vec![(parties.seller, 10_000)], terms)
+}
+
+#[ink::test]
+fn confirmed_deal_pays_the_seller() {
+    let parties = parties();
+    let mut escrow = instantiate(&parties, Terms::default());
+
+    call_as(parties.buyer, 0);
+    escrow.confirm().expect("Confirm failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE - DEPOSIT);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE + DEPOSIT);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn refunded_deal_pays_the_buyer_back() {
+    let parties = parties();
+    let mut escrow = instantiate(&parties, Terms::default());
+
+    call_as(parties.seller, 0);
+    escrow.refund().expect("Refund failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn confirmed_deal_pays_the_fee_to_the_collector() {
+    let parties = parties();
+    let terms = Terms {
+        fee: Some(Fee {
+            collector: parties.collector,
+            basis_points: 250,
+        }),
+        ..Default::default()
+    };
+    let mut escrow = instantiate(&parties, terms);
+
+    call_as(parties.buyer, 0);
+    escrow.confirm().expect("Confirm failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE - DEPOSIT);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE + 98);
+    assert_eq!(balance(parties.collector), INITIAL_BALANCE + 2);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn resolved_dispute_splits_the_deposit() {
+    let parties = parties();
+    let terms = Terms {
+        arbiter: Some(parties.arbiter),
+        ..Default::default()
+    };
+    let mut escrow = instantiate(&parties, terms);
+
+    call_as(parties.seller, 0);
+    escrow.raise_dispute().expect("Raise dispute failed!");
+    call_as(parties.arbiter, 0);
+    escrow.resolve(30, 70).expect("Resolve failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE - 70);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE + 70);
+    assert_eq!(balance(parties.arbiter), INITIAL_BALANCE);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn confirmed_deal_returns_the_collateral() {
+    const COLLATERAL: Balance = 50;
+    let parties = parties();
+    let terms = Terms {
+        collateral: COLLATERAL,
+        ..Default::default()
+    };
+    let mut escrow = instantiate(&parties, terms);
+
+    call_as(parties.seller, COLLATERAL);
+    escrow.accept().expect("Accept failed!");
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE - COLLATERAL);
+    assert_eq!(balance(contract_id()), DEPOSIT + COLLATERAL);
+
+    call_as(parties.buyer, 0);
+    escrow.confirm().expect("Confirm failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE - DEPOSIT);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE + DEPOSIT);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn contributors_are_refunded_what_they_paid() {
+    let parties = parties();
+    let terms = Terms {
+        contributors: vec![parties.collector],
+        ..Default::default()
+    };
+    let mut escrow = instantiate(&parties, terms);
+
+    call_as(parties.collector, 300);
+    escrow.contribute().expect("Contribute failed!");
+    call_as(parties.seller, 0);
+    escrow.refund().expect("Refund failed!");
+
+    assert_eq!(balance(parties.buyer), INITIAL_BALANCE);
+    assert_eq!(balance(parties.collector), INITIAL_BALANCE);
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE);
+    assert_eq!(balance(contract_id()), 0);
+}
+
+#[ink::test]
+fn closed_deal_returns_the_residue_to_the_buyer() {
+    // stands for the storage deposit, which the buyer pays when instantiating the contract
+    const STORAGE_DEPOSIT: Balance = 7;
+    const STRAY_TRANSFER: Balance = 3;
+    let parties = parties();
+    let mut escrow = instantiate(&parties, Terms::default());
+    transfer_directly(parties.buyer, STORAGE_DEPOSIT);
+    transfer_directly(parties.collector, STRAY_TRANSFER);
+
+    call_as(parties.buyer, 0);
+    escrow.confirm().expect("Confirm failed!");
+    assert_eq!(balance(contract_id()), STORAGE_DEPOSIT + STRAY_TRANSFER);
+
+    let buyer = parties.buyer;
+    let should_terminate = move || {
+        call_as(buyer, 0);
+        escrow.close().expect("Close failed!")
+    };
+    test::assert_contract_termination::<DefaultEnvironment, _>(
+        should_terminate,
+        buyer,
+        STORAGE_DEPOSIT + STRAY_TRANSFER,
+    );
+
+    assert_eq!(
+        balance(parties.buyer),
+        INITIAL_BALANCE - DEPOSIT + STRAY_TRANSFER
+    );
+    assert_eq!(balance(parties.seller), INITIAL_BALANCE + DEPOSIT);
+    assert_eq!(balance(parties.collector), INITIAL_BALANCE - STRAY_TRANSFER);
+    assert_eq!(balance(contract_id()), 0);
+}
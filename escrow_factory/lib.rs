@@ -0,0 +1,392 @@
+//! # Escrow factory
+//!
+//! This implements a factory of Escrow contracts.
+//!
+//! ## Warning
+//!
+//! This contract is an *example*. It is neither audited nor endorsed for production use.
+//! Do **not** rely on it to keep anything of value secure.
+//!
+//! ## Overview
+//!
+//! Instead of deploying `escrow` manually for every deal, the buyer calls `create_escrow()` on the
+//! factory, transferring the deposit along. The factory instantiates a new `escrow` contract from
+//! the stored code hash, on behalf of the buyer, and passes the deposit on.
+//!
+//! Every escrow created is recorded in a registry, indexed both by buyer and by seller (every payee
+//! counts as a seller). The registry can be queried page by page, so that a front-end can list
+//! deals of a given account.
+//!
+//! The registry lists all deals ever created, in order of creation, and is never pruned: the factory
+//! is not notified when a deal is settled or closed. To list active deals only, the factory filters
+//! a page of the registry by querying `get_state()` of every escrow on it - a settled deal reports
+//! its final state, and a closed one no longer exists, so the call fails. A filtered page can hence
+//! hold fewer escrows than requested, even none, while the following pages still hold some.
+//!
+//! Only the owner of the factory (its instantiator) can change the code hash of escrows created
+//! from now on. Escrows which are already created are not affected.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+
+#[ink::contract]
+pub mod escrow_factory {
+    use escrow::escrow::{EscrowRef, State, Terms};
+    use ink_env::call::{build_call, build_create, Call, ExecutionInput, Selector};
+    use ink_lang::ToAccountId;
+    use ink_prelude::vec::Vec;
+    use ink_storage::{traits::SpreadAllocate, Mapping};
+    use scale::{Decode, Encode};
+
+    /// Selector of `Escrow::new`
+    const ESCROW_NEW_SELECTOR: [u8; 4] = [0x9b, 0xae, 0x9d, 0x5e];
+
+    /// Selector of `Escrow::get_state`
+    const ESCROW_GET_STATE_SELECTOR: [u8; 4] = [0x3d, 0x1e, 0x89, 0x68];
+
+    #[ink(storage)]
+    #[derive(SpreadAllocate)]
+    pub struct EscrowFactory {
+        /// Owner of the factory, can change the code hash of created escrows
+        owner: AccountId,
+
+        /// Code hash of the `escrow` contract
+        escrow_code_hash: Hash,
+
+        /// Number of escrows created so far, used as a salt for instantiation
+        escrow_count: u64,
+
+        /// Number of escrows created by each buyer
+        buyer_escrow_count: Mapping<AccountId, u32>,
+
+        /// Escrows of each buyer, indexed in order of creation
+        buyer_escrows: Mapping<(AccountId, u32), AccountId>,
+
+        /// Number of escrows created for each seller
+        seller_escrow_count: Mapping<AccountId, u32>,
+
+        /// Escrows of each seller, indexed in order of creation
+        seller_escrows: Mapping<(AccountId, u32), AccountId>,
+    }
+
+    /// Error scenarios in escrow factory contract
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Someone else than owner tries to change the code hash
+        PermissionDenied,
+
+        /// Instantiation of the escrow contract failed
+        InstantiationFailed,
+
+        /// Escrow is requested without any payees
+        NoPayees,
+    }
+
+    /// An event emitted when a new escrow is created
+    #[ink(event)]
+    pub struct EscrowCreated {
+        #[ink(topic)]
+        escrow: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        seller: AccountId,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl EscrowFactory {
+        /// Instantiates new factory creating escrows with a given code hash
+        #[ink(constructor)]
+        pub fn new(escrow_code_hash: Hash) -> Self {
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.owner = Self::env().caller();
+                contract.escrow_code_hash = escrow_code_hash;
+            })
+        }
+
+        /// Returns code hash of created escrows
+        #[ink(message)]
+        pub fn get_escrow_code_hash(&self) -> Hash {
+            self.escrow_code_hash
+        }
+
+        /// Allows owner to change code hash of escrows created from now on
+        #[ink(message)]
+        pub fn set_escrow_code_hash(&mut self, escrow_code_hash: Hash) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.escrow_code_hash = escrow_code_hash;
+            Ok(())
+        }
+
+        /// Creates a new escrow with the caller as the buyer. Transferred value becomes the deposit.
+        /// Payees are given with their shares in basis points, the first one being the lead seller.
+        /// Returns account of the created escrow
+        #[ink(message, payable)]
+        pub fn create_escrow(
+            &mut self,
+            payees: Vec<(AccountId, u16)>,
+            terms: Terms,
+        ) -> Result<AccountId> {
+            let seller = payees.first().ok_or(Error::NoPayees)?.0;
+            let buyer = self.env().caller();
+            let terms = Terms {
+                buyer: Some(buyer),
+                ..terms
+            };
+            let escrow = build_create::<ink_env::DefaultEnvironment, EscrowRef>()
+                .code_hash(self.escrow_code_hash)
+                .gas_limit(0)
+                .endowment(self.env().transferred_value())
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ESCROW_NEW_SELECTOR))
+                        .push_arg(&payees)
+                        .push_arg(terms),
+                )
+                .salt_bytes(self.escrow_count.to_le_bytes())
+                .params()
+                .instantiate()
+                .map_err(|_| Error::InstantiationFailed)?
+                .to_account_id();
+
+            self.escrow_count += 1;
+            self.register(buyer, seller, escrow);
+            for (payee, _) in payees.iter().skip(1) {
+                self.register_seller(*payee, escrow);
+            }
+            self.env().emit_event(EscrowCreated {
+                escrow,
+                buyer,
+                seller,
+            });
+            Ok(escrow)
+        }
+
+        /// Returns number of escrows created by a buyer, including settled and closed ones
+        #[ink(message)]
+        pub fn get_buyer_escrow_count(&self, buyer: AccountId) -> u32 {
+            self.buyer_escrow_count.get(buyer).unwrap_or_default()
+        }
+
+        /// Returns at most `limit` escrows created by a buyer, starting from `from`-th one. Settled
+        /// and closed escrows are listed as well
+        #[ink(message)]
+        pub fn get_buyer_escrows(&self, buyer: AccountId, from: u32, limit: u32) -> Vec<AccountId> {
+            let count = self.get_buyer_escrow_count(buyer);
+            Self::page(&self.buyer_escrows, buyer, count, from, limit)
+        }
+
+        /// Returns number of escrows created for a seller, including settled and closed ones
+        #[ink(message)]
+        pub fn get_seller_escrow_count(&self, seller: AccountId) -> u32 {
+            self.seller_escrow_count.get(seller).unwrap_or_default()
+        }
+
+        /// Returns at most `limit` escrows created for a seller, starting from `from`-th one. Settled
+        /// and closed escrows are listed as well
+        #[ink(message)]
+        pub fn get_seller_escrows(
+            &self,
+            seller: AccountId,
+            from: u32,
+            limit: u32,
+        ) -> Vec<AccountId> {
+            let count = self.get_seller_escrow_count(seller);
+            Self::page(&self.seller_escrows, seller, count, from, limit)
+        }
+
+        /// Returns those of at most `limit` escrows created by a buyer, starting from `from`-th one,
+        /// which are neither settled nor closed
+        #[ink(message)]
+        pub fn get_active_buyer_escrows(
+            &self,
+            buyer: AccountId,
+            from: u32,
+            limit: u32,
+        ) -> Vec<AccountId> {
+            Self::active(
+                self.get_buyer_escrows(buyer, from, limit),
+                Self::get_escrow_state,
+            )
+        }
+
+        /// Returns those of at most `limit` escrows created for a seller, starting from `from`-th
+        /// one, which are neither settled nor closed
+        #[ink(message)]
+        pub fn get_active_seller_escrows(
+            &self,
+            seller: AccountId,
+            from: u32,
+            limit: u32,
+        ) -> Vec<AccountId> {
+            Self::active(
+                self.get_seller_escrows(seller, from, limit),
+                Self::get_escrow_state,
+            )
+        }
+
+        fn register(&mut self, buyer: AccountId, seller: AccountId, escrow: AccountId) {
+            let buyer_count = self.get_buyer_escrow_count(buyer);
+            self.buyer_escrows.insert((buyer, buyer_count), &escrow);
+            self.buyer_escrow_count.insert(buyer, &(buyer_count + 1));
+
+            self.register_seller(seller, escrow);
+        }
+
+        fn register_seller(&mut self, seller: AccountId, escrow: AccountId) {
+            let seller_count = self.get_seller_escrow_count(seller);
+            self.seller_escrows.insert((seller, seller_count), &escrow);
+            self.seller_escrow_count.insert(seller, &(seller_count + 1));
+        }
+
+        fn page(
+            escrows: &Mapping<(AccountId, u32), AccountId>,
+            account: AccountId,
+            count: u32,
+            from: u32,
+            limit: u32,
+        ) -> Vec<AccountId> {
+            (from..count.min(from.saturating_add(limit)))
+                .filter_map(|index| escrows.get((account, index)))
+                .collect()
+        }
+
+        /// Keeps escrows which are neither settled nor closed, given their states
+        fn active(
+            escrows: Vec<AccountId>,
+            get_state: impl Fn(AccountId) -> Option<State>,
+        ) -> Vec<AccountId> {
+            escrows
+                .into_iter()
+                .filter(|escrow| {
+                    matches!(
+                        get_state(*escrow),
+                        Some(State::Created | State::Funded | State::Disputed)
+                    )
+                })
+                .collect()
+        }
+
+        /// Returns state of an escrow, or `None` if the call fails, i.e. the escrow is closed
+        fn get_escrow_state(escrow: AccountId) -> Option<State> {
+            build_call::<ink_env::DefaultEnvironment>()
+                .call_type(Call::new().callee(escrow))
+                .exec_input(ExecutionInput::new(Selector::new(
+                    ESCROW_GET_STATE_SELECTOR,
+                )))
+                .returns::<State>()
+                .fire()
+                .ok()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink_env::test::{default_accounts, set_caller};
+        use ink_env::DefaultEnvironment;
+        use ink_lang as ink;
+
+        fn escrow_id(id: u8) -> AccountId {
+            AccountId::from([id; 32])
+        }
+
+        #[ink::test]
+        fn non_owner_cannot_change_code_hash() {
+            let accounts = default_accounts::<DefaultEnvironment>();
+            set_caller::<DefaultEnvironment>(accounts.alice);
+            let mut factory = EscrowFactory::new(Hash::from([1; 32]));
+
+            set_caller::<DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                factory.set_escrow_code_hash(Hash::from([2; 32])),
+                Err(Error::PermissionDenied)
+            );
+            assert_eq!(factory.get_escrow_code_hash(), Hash::from([1; 32]));
+        }
+
+        #[ink::test]
+        fn owner_can_change_code_hash() {
+            let accounts = default_accounts::<DefaultEnvironment>();
+            set_caller::<DefaultEnvironment>(accounts.alice);
+            let mut factory = EscrowFactory::new(Hash::from([1; 32]));
+
+            assert!(factory.set_escrow_code_hash(Hash::from([2; 32])).is_ok());
+            assert_eq!(factory.get_escrow_code_hash(), Hash::from([2; 32]));
+        }
+
+        #[ink::test]
+        fn escrows_are_registered_by_buyer_and_seller() {
+            let accounts = default_accounts::<DefaultEnvironment>();
+            let mut factory = EscrowFactory::new(Hash::from([1; 32]));
+
+            factory.register(accounts.bob, accounts.charlie, escrow_id(1));
+            factory.register(accounts.bob, accounts.django, escrow_id(2));
+            factory.register(accounts.eve, accounts.charlie, escrow_id(3));
+
+            assert_eq!(factory.get_buyer_escrow_count(accounts.bob), 2);
+            assert_eq!(
+                factory.get_buyer_escrows(accounts.bob, 0, 10),
+                vec![escrow_id(1), escrow_id(2)]
+            );
+            assert_eq!(factory.get_seller_escrow_count(accounts.charlie), 2);
+            assert_eq!(
+                factory.get_seller_escrows(accounts.charlie, 0, 10),
+                vec![escrow_id(1), escrow_id(3)]
+            );
+            assert_eq!(factory.get_buyer_escrow_count(accounts.frank), 0);
+            assert!(factory.get_buyer_escrows(accounts.frank, 0, 10).is_empty());
+        }
+
+        #[ink::test]
+        fn only_escrows_neither_settled_nor_closed_are_active() {
+            let escrows = (0..6).map(escrow_id).collect();
+            let states = |escrow: AccountId| match escrow {
+                escrow if escrow == escrow_id(0) => Some(State::Created),
+                escrow if escrow == escrow_id(1) => Some(State::Funded),
+                escrow if escrow == escrow_id(2) => Some(State::Disputed),
+                escrow if escrow == escrow_id(3) => Some(State::Released),
+                escrow if escrow == escrow_id(4) => Some(State::Refunded),
+                _ => None,
+            };
+
+            assert_eq!(
+                EscrowFactory::active(escrows, states),
+                vec![escrow_id(0), escrow_id(1), escrow_id(2)]
+            );
+        }
+
+        #[ink::test]
+        fn escrows_are_paginated() {
+            let accounts = default_accounts::<DefaultEnvironment>();
+            let mut factory = EscrowFactory::new(Hash::from([1; 32]));
+            for id in 0..5 {
+                factory.register(accounts.bob, accounts.charlie, escrow_id(id));
+            }
+
+            assert_eq!(
+                factory.get_buyer_escrows(accounts.bob, 0, 2),
+                vec![escrow_id(0), escrow_id(1)]
+            );
+            assert_eq!(
+                factory.get_buyer_escrows(accounts.bob, 2, 2),
+                vec![escrow_id(2), escrow_id(3)]
+            );
+            assert_eq!(
+                factory.get_buyer_escrows(accounts.bob, 4, 2),
+                vec![escrow_id(4)]
+            );
+            assert!(factory.get_buyer_escrows(accounts.bob, 5, 2).is_empty());
+            assert_eq!(
+                factory
+                    .get_seller_escrows(accounts.charlie, 3, u32::MAX)
+                    .len(),
+                2
+            );
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! # PSP22 Escrow
+//!
+//! This implements an Escrow contract holding PSP22 tokens.
+//!
+//! ## Warning
+//!
+//! This contract is an *example*. It is neither audited nor endorsed for production use.
+//! Do **not** rely on it to keep anything of value secure.
+//!
+//! ## Overview
+//!
+//! This is a variant of the `escrow` contract, where the deposit is made in PSP22 tokens
+//! instead of native currency. Parties, as before, are the buyer and the seller. The contract
+//! is instantiated by the buyer with the address of the token contract and the deposit value.
+//!
+//! Since the address of the contract is not known before instantiation, the deposit is pulled
+//! in a separate step:
+//! * the buyer approves the escrow contract to spend the deposit on the token contract, and
+//! * the buyer calls `fund()`, which moves the deposit with `PSP22::transfer_from`.
+//!
+//! Once funded, the contract can end up in two ways:
+//! * either delivery of the goods is confirmed by the buyer -> a deposit is transferred to the seller, or
+//! * delivery is cancelled by the seller -> a deposit is refunded to the buyer.
+//! In each way contract terminates itself and its native balance, including the storage deposit,
+//! is transferred to the buyer.
+//!
+//! Failures reported by the token contract when funding are surfaced as distinct errors, so that
+//! e.g. missing approval can be told apart from a trapped call.
+//!
+//! The contract records that it is funded only once the deposit is pulled, and that it is no longer
+//! funded before paying the deposit out. If the payout fails, the call traps, so that the contract
+//! stays funded - an error would not revert the change. The calls to the token contract explicitly
+//! deny reentrancy, so the deposit cannot be pulled or paid out twice.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+
+#[ink::contract]
+pub mod escrow_psp22 {
+    use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+    use ink_env::CallFlags;
+    use ink_prelude::{string::String, vec::Vec};
+    use scale::{Decode, Encode};
+
+    /// Selector of `PSP22::transfer`
+    const PSP22_TRANSFER_SELECTOR: [u8; 4] = [0xdb, 0x20, 0xf9, 0xf5];
+
+    /// Selector of `PSP22::transfer_from`
+    const PSP22_TRANSFER_FROM_SELECTOR: [u8; 4] = [0x54, 0xb3, 0xc7, 0x6e];
+
+    #[ink(storage)]
+    pub struct EscrowPsp22 {
+        /// Buyer's account
+        buyer: AccountId,
+
+        /// Sellers's account
+        seller: AccountId,
+
+        /// PSP22 token contract in which the deposit is made
+        token: AccountId,
+
+        /// Deposit value, in tokens
+        deposit: Balance,
+
+        /// Whether the deposit has been pulled from the buyer
+        funded: bool,
+    }
+
+    /// Errors returned by PSP22 token contracts
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum PSP22Error {
+        Custom(String),
+        InsufficientBalance,
+        InsufficientAllowance,
+        ZeroRecipientAddress,
+        ZeroSenderAddress,
+        SafeTransferCheckFailed(String),
+    }
+
+    /// Error scenarios in escrow contract
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Someone else than buyer tries to confirm
+        ConfirmNotAsBuyer,
+
+        /// Someone else than seller tries to refund
+        RefundNotAsSeller,
+
+        /// Someone else than buyer tries to fund the contract
+        FundNotAsBuyer,
+
+        /// Deposit has already been pulled from the buyer
+        AlreadyFunded,
+
+        /// Deposit has not been pulled from the buyer yet
+        NotFunded,
+
+        /// Token contract reports that the sender does not hold enough tokens
+        InsufficientTokenBalance,
+
+        /// Token contract reports that the escrow has not been approved to spend enough tokens
+        InsufficientTokenAllowance,
+
+        /// Token contract rejects the transfer for another reason
+        TokenTransferRejected(PSP22Error),
+
+        /// Call to the token contract failed, e.g. the contract does not exist or it trapped
+        TokenCallFailed,
+    }
+
+    impl From<PSP22Error> for Error {
+        fn from(error: PSP22Error) -> Self {
+            match error {
+                PSP22Error::InsufficientBalance => Error::InsufficientTokenBalance,
+                PSP22Error::InsufficientAllowance => Error::InsufficientTokenAllowance,
+                error => Error::TokenTransferRejected(error),
+            }
+        }
+    }
+
+    /// An event emitted when token transfer occurs
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        to: AccountId,
+        value: Balance,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl EscrowPsp22 {
+        /// Instantiates new escrow contract with buyer as contract author. The deposit of `value`
+        /// tokens is pulled from the buyer with `fund()`
+        #[ink(constructor)]
+        pub fn new(seller: AccountId, token: AccountId, value: Balance) -> Self {
+            Self {
+                buyer: Self::env().caller(),
+                seller,
+                token,
+                deposit: value,
+                funded: false,
+            }
+        }
+
+        /// Returns currently stored deposit
+        #[ink(message)]
+        pub fn get_deposit(&self) -> Balance {
+            self.deposit
+        }
+
+        /// Returns buyers account
+        #[ink(message)]
+        pub fn get_buyer(&self) -> AccountId {
+            self.buyer
+        }
+
+        /// Returns sellers account
+        #[ink(message)]
+        pub fn get_seller(&self) -> AccountId {
+            self.seller
+        }
+
+        /// Returns token contract account
+        #[ink(message)]
+        pub fn get_token(&self) -> AccountId {
+            self.token
+        }
+
+        /// Returns whether the deposit has been pulled from the buyer
+        #[ink(message)]
+        pub fn is_funded(&self) -> bool {
+            self.funded
+        }
+
+        /// The buyer funds the contract. Contract pulls a deposit from the buyer, who must have
+        /// approved the contract to spend it beforehand
+        #[ink(message)]
+        pub fn fund(&mut self) -> Result<()> {
+            if Self::env().caller() != self.buyer {
+                return Err(Error::FundNotAsBuyer);
+            }
+            if self.funded {
+                return Err(Error::AlreadyFunded);
+            }
+            self.token_transfer_from(self.buyer, self.env().account_id(), self.deposit)?;
+            self.funded = true;
+            self.env().emit_event(Transfer {
+                to: self.env().account_id(),
+                value: self.deposit,
+            });
+            Ok(())
+        }
+
+        /// The buyer confirms delivery. Contract transfers a deposit to the seller and terminates itself
+        #[ink(message)]
+        pub fn confirm(&mut self) -> Result<()> {
+            if Self::env().caller() != self.buyer {
+                return Err(Error::ConfirmNotAsBuyer);
+            }
+            if !self.funded {
+                return Err(Error::NotFunded);
+            }
+            self.funded = false;
+            self.make_transfer(self.seller, self.deposit);
+            self.env().terminate_contract(self.buyer)
+        }
+
+        /// The seller aborts transaction. Contract refunds a deposit to the buyer, if it has been
+        /// funded, and terminates itself
+        #[ink(message)]
+        pub fn refund(&mut self) -> Result<()> {
+            if Self::env().caller() != self.seller {
+                return Err(Error::RefundNotAsSeller);
+            }
+            if self.funded {
+                self.funded = false;
+                self.make_transfer(self.buyer, self.deposit);
+            }
+            self.env().terminate_contract(self.buyer)
+        }
+
+        /// Pays out `value` tokens to `to`. Traps on failure, reverting the whole call
+        fn make_transfer(&mut self, to: AccountId, value: Balance) {
+            if let Err(error) = self.token_transfer(to, value) {
+                panic!("Payout failed: {:?}", error)
+            }
+            self.env().emit_event(Transfer { to, value });
+        }
+
+        fn token_transfer(&self, to: AccountId, value: Balance) -> Result<()> {
+            build_call::<ink_env::DefaultEnvironment>()
+                .call_type(Call::new().callee(self.token))
+                .call_flags(CallFlags::default().set_allow_reentry(false))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(PSP22_TRANSFER_SELECTOR))
+                        .push_arg(to)
+                        .push_arg(value)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<core::result::Result<(), PSP22Error>>()
+                .fire()
+                .map_err(|_| Error::TokenCallFailed)?
+                .map_err(Error::from)
+        }
+
+        fn token_transfer_from(
+            &self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        ) -> Result<()> {
+            build_call::<ink_env::DefaultEnvironment>()
+                .call_type(Call::new().callee(self.token))
+                .call_flags(CallFlags::default().set_allow_reentry(false))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(PSP22_TRANSFER_FROM_SELECTOR))
+                        .push_arg(from)
+                        .push_arg(to)
+                        .push_arg(value)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<core::result::Result<(), PSP22Error>>()
+                .fire()
+                .map_err(|_| Error::TokenCallFailed)?
+                .map_err(Error::from)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_env::AccountId;
+
+        /// Imports `ink_lang` so we can use `#[ink::test]`.
+        use ink_lang as ink;
+
+        fn get_default_test_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment>
+        {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+        }
+
+        fn set_caller(caller: AccountId) {
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(caller);
+        }
+
+        fn create_contract(deposit: Balance) -> (EscrowPsp22, AccountId, AccountId) {
+            let accounts = get_default_test_accounts();
+            let buyer = accounts.eve;
+            let seller = accounts.frank;
+
+            set_caller(buyer);
+            let escrow = EscrowPsp22::new(seller, accounts.django, deposit);
+
+            (escrow, buyer, seller)
+        }
+
+        #[ink::test]
+        fn when_constructor_is_called_then_contract_is_not_funded() {
+            let (escrow, buyer, seller) = create_contract(123);
+            assert_eq!(escrow.get_buyer(), buyer);
+            assert_eq!(escrow.get_seller(), seller);
+            assert_eq!(escrow.get_token(), get_default_test_accounts().django);
+            assert_eq!(escrow.get_deposit(), 123);
+            assert!(!escrow.is_funded());
+        }
+
+        #[ink::test]
+        fn when_not_buyer_funds_then_error_is_returned() {
+            let (mut escrow, _, seller) = create_contract(10);
+            set_caller(seller);
+            assert_eq!(escrow.fund(), Err(Error::FundNotAsBuyer));
+        }
+
+        #[ink::test]
+        fn when_not_funded_then_buyer_cannot_confirm() {
+            let (mut escrow, buyer, seller) = create_contract(10);
+            set_caller(seller);
+            assert_eq!(escrow.confirm(), Err(Error::ConfirmNotAsBuyer));
+            set_caller(buyer);
+            assert_eq!(escrow.confirm(), Err(Error::NotFunded));
+        }
+
+        #[ink::test]
+        fn when_not_funded_then_seller_refund_only_terminates_contract() {
+            let (mut escrow, buyer, seller) = create_contract(10);
+            set_caller(buyer);
+            assert_eq!(escrow.refund(), Err(Error::RefundNotAsSeller));
+
+            let should_terminate = move || {
+                set_caller(seller);
+                escrow.refund().expect("Refund failed!")
+            };
+            ink_env::test::assert_contract_termination::<ink_env::DefaultEnvironment, _>(
+                should_terminate,
+                buyer,
+                ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(
+                    ink_env::test::callee::<ink_env::DefaultEnvironment>(),
+                )
+                .expect("Cannot get account balance"),
+            );
+        }
+
+        #[ink::test]
+        fn token_errors_are_mapped_to_distinct_variants() {
+            assert_eq!(
+                Error::from(PSP22Error::InsufficientBalance),
+                Error::InsufficientTokenBalance
+            );
+            assert_eq!(
+                Error::from(PSP22Error::InsufficientAllowance),
+                Error::InsufficientTokenAllowance
+            );
+            assert_eq!(
+                Error::from(PSP22Error::ZeroRecipientAddress),
+                Error::TokenTransferRejected(PSP22Error::ZeroRecipientAddress)
+            );
+        }
+    }
+}